@@ -0,0 +1,17 @@
+use std::io::Result;
+
+fn main() -> Result<()> {
+    // protos/heekkr holds copies of the spec files imported by ours; their
+    // types come from the `heekkr` crate.
+    tonic_build::configure()
+        .extern_path(".kr.heek.LatLng", "::heekkr::kr::heek::LatLng")
+        .extern_path(".kr.heek.Library", "::heekkr::kr::heek::Library")
+        .compile(
+            &[
+                "protos/jsonrs/diagnostics.proto",
+                "protos/jsonrs/libraries.proto",
+            ],
+            &["protos/"],
+        )?;
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{sync::watch, time::Instant};
+
+use crate::config::{BreakerConfig, Config};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// Requests pass.
+    Closed,
+    /// Requests are rejected until the cool-down ends.
+    Open { until: Instant },
+    /// One probe request was let through. If it has not finished by `until`,
+    /// e.g. because its client went away, another one may go.
+    HalfOpen { until: Instant },
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: State,
+    /// Consecutive failures, still counted while the circuit is open.
+    failures: u32,
+}
+
+/// Circuit breaker for one resolver, shared by every request to it.
+#[derive(Debug)]
+pub struct Breaker {
+    config: BreakerConfig,
+    inner: Mutex<Inner>,
+    /// Whether the circuit is closed, for those watching it.
+    closed: watch::Sender<bool>,
+}
+
+impl Breaker {
+    pub fn new(config: BreakerConfig) -> Breaker {
+        Breaker {
+            config,
+            inner: Mutex::new(Inner {
+                state: State::Closed,
+                failures: 0,
+            }),
+            closed: watch::channel(true).0,
+        }
+    }
+
+    /// Whether a request may go upstream. Moves an open circuit whose
+    /// cool-down has ended to half-open, letting this request probe.
+    pub fn allow(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        match inner.state {
+            State::Closed => true,
+            State::Open { until } | State::HalfOpen { until } if now < until => false,
+            State::Open { .. } | State::HalfOpen { .. } => {
+                inner.state = State::HalfOpen {
+                    until: now + self.cooldown(),
+                };
+                self.notify(&inner);
+                true
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state = State::Closed;
+        inner.failures = 0;
+        self.notify(&inner);
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.failures += 1;
+        if inner.state != State::Closed || inner.failures >= self.config.failure_threshold {
+            inner.state = State::Open {
+                until: Instant::now() + self.cooldown(),
+            };
+            self.notify(&inner);
+        }
+    }
+
+    /// The current state and the number of consecutive failures.
+    pub fn status(&self) -> (State, u32) {
+        let inner = self.inner.lock().unwrap();
+        (inner.state, inner.failures)
+    }
+
+    /// Turns false while the circuit is open or half-open.
+    pub fn closed(&self) -> watch::Receiver<bool> {
+        self.closed.subscribe()
+    }
+
+    fn notify(&self, inner: &Inner) {
+        let closed = inner.state == State::Closed;
+        self.closed
+            .send_if_modified(|c| std::mem::replace(c, closed) != closed);
+    }
+
+    fn cooldown(&self) -> Duration {
+        Duration::from_millis(self.config.cooldown_ms)
+    }
+}
+
+/// Breakers of every configured resolver, by resolver id.
+pub struct Breakers(HashMap<String, Arc<Breaker>>);
+
+impl Breakers {
+    pub fn from_config(config: &Config) -> Breakers {
+        Breakers(
+            config
+                .resolvers
+                .iter()
+                .map(|c| (c.id.clone(), Arc::new(Breaker::new(c.breaker.clone()))))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, id: &str) -> Arc<Breaker> {
+        self.0[id].clone()
+    }
+
+    /// Breakers sorted by resolver id.
+    pub fn iter(&self) -> Vec<(&String, &Arc<Breaker>)> {
+        let mut breakers = self.0.iter().collect::<Vec<_>>();
+        breakers.sort_by_key(|(id, _)| *id);
+        breakers
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{watch, Mutex, MutexGuard};
+
+use crate::{config::Config, proto::LibraryDetail};
+
+struct Entry {
+    libraries: Vec<LibraryDetail>,
+    loaded: Instant,
+}
+
+#[derive(Default)]
+struct Slot {
+    entry: RwLock<Option<Entry>>,
+    /// Held while loading, so concurrent requests wait for one upstream call
+    /// instead of each making their own.
+    loading: Mutex<()>,
+}
+
+/// Library lists of every resolver, kept for the configured TTL and served
+/// past it while the upstream fails.
+pub struct Catalogue {
+    ttl: Duration,
+    slots: HashMap<String, Slot>,
+    /// Whether any library list has been loaded yet, or there are none to
+    /// load.
+    loaded: watch::Sender<bool>,
+}
+
+impl Catalogue {
+    pub fn new(config: &Config) -> Catalogue {
+        Catalogue {
+            ttl: Duration::from_secs(config.libraries.ttl_secs),
+            loaded: watch::channel(config.resolvers.is_empty()).0,
+            slots: config
+                .resolvers
+                .iter()
+                .map(|c| (c.id.clone(), Slot::default()))
+                .collect(),
+        }
+    }
+
+    /// Libraries of resolver `id` loaded within the TTL.
+    pub fn fresh(&self, id: &str) -> Option<Vec<LibraryDetail>> {
+        let entry = self.slots[id].entry.read().unwrap();
+        entry
+            .as_ref()
+            .filter(|e| e.loaded.elapsed() < self.ttl)
+            .map(|e| e.libraries.clone())
+    }
+
+    /// Libraries of resolver `id` from the last successful load, however old.
+    pub fn stale(&self, id: &str) -> Option<Vec<LibraryDetail>> {
+        let entry = self.slots[id].entry.read().unwrap();
+        entry.as_ref().map(|e| e.libraries.clone())
+    }
+
+    pub fn store(&self, id: &str, libraries: Vec<LibraryDetail>) {
+        *self.slots[id].entry.write().unwrap() = Some(Entry {
+            libraries,
+            loaded: Instant::now(),
+        });
+        self.loaded
+            .send_if_modified(|loaded| !std::mem::replace(loaded, true));
+    }
+
+    /// Turns true once any library list has been loaded, or right away if
+    /// there are none to load.
+    pub fn loaded(&self) -> watch::Receiver<bool> {
+        self.loaded.subscribe()
+    }
+
+    pub async fn lock(&self, id: &str) -> MutexGuard<'_, ()> {
+        self.slots[id].loading.lock().await
+    }
+}
@@ -0,0 +1,520 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use url::Url;
+
+use crate::{jsonpath::JsonPath, resolver::Fingerprint};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(rename = "resolver", default)]
+    pub resolvers: Vec<ResolverConfig>,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub libraries: CatalogueConfig,
+    #[serde(default)]
+    pub geocode: GeocodeConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeocodeConfig {
+    /// Tried in order until one finds the keyword.
+    #[serde(rename = "provider", default = "default_geocode_providers")]
+    pub providers: Vec<GeocodeProvider>,
+    #[serde(default)]
+    pub cache: GeocodeCacheConfig,
+    /// TOML file with coordinates used instead of geocoding for listed
+    /// libraries, resolved against the config file's directory.
+    pub overrides: Option<PathBuf>,
+}
+
+impl Default for GeocodeConfig {
+    fn default() -> Self {
+        GeocodeConfig {
+            providers: default_geocode_providers(),
+            cache: GeocodeCacheConfig::default(),
+            overrides: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum GeocodeProvider {
+    /// Kakao local keyword search, keyed by `KAKAO_API_KEY`.
+    Kakao,
+    /// VWorld place search, keyed by `VWORLD_API_KEY`.
+    Vworld,
+    /// A local TOML file of `[[place]]` names and coordinates, resolved
+    /// against the config file's directory.
+    Gazetteer { path: PathBuf },
+}
+
+fn default_geocode_providers() -> Vec<GeocodeProvider> {
+    vec![GeocodeProvider::Kakao]
+}
+
+/// Where geocoded library coordinates are kept between lookups.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GeocodeCacheConfig {
+    #[serde(default = "default_geocode_ttl_secs")]
+    pub ttl_secs: u64,
+    /// How long keywords no provider could find are left alone, kept in
+    /// memory only. Zero looks them up every time.
+    #[serde(default = "default_not_found_ttl_secs")]
+    pub not_found_ttl_secs: u64,
+    #[serde(flatten)]
+    pub backend: GeocodeCacheBackend,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GeocodeCacheBackend {
+    /// Lost on restart.
+    Memory,
+    /// A JSON file, resolved against the config file's directory.
+    Disk { path: PathBuf },
+    /// Objects under `prefix` in the bucket named by `CACHED_GCS_BUCKET`.
+    /// Requires the `gcs` feature.
+    Gcs {
+        #[serde(default = "default_gcs_prefix")]
+        #[cfg_attr(not(feature = "gcs"), allow(dead_code))]
+        prefix: String,
+    },
+}
+
+/// GCS when built with the `gcs` feature, memory otherwise.
+impl Default for GeocodeCacheConfig {
+    fn default() -> Self {
+        GeocodeCacheConfig {
+            ttl_secs: default_geocode_ttl_secs(),
+            not_found_ttl_secs: default_not_found_ttl_secs(),
+            backend: if cfg!(feature = "gcs") {
+                GeocodeCacheBackend::Gcs {
+                    prefix: default_gcs_prefix(),
+                }
+            } else {
+                GeocodeCacheBackend::Memory
+            },
+        }
+    }
+}
+
+fn default_geocode_ttl_secs() -> u64 {
+    60 * 60 * 24 * 30
+}
+
+fn default_not_found_ttl_secs() -> u64 {
+    60 * 60 * 6
+}
+
+fn default_gcs_prefix() -> String {
+    "kakao-search-keyword/".to_owned()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CatalogueConfig {
+    /// How long a loaded library list is served without asking upstream.
+    pub ttl_secs: u64,
+    /// Interval at which `serve` reloads every library list in the
+    /// background. Zero loads them once at startup, then only as they
+    /// expire.
+    pub refresh_secs: u64,
+}
+
+impl Default for CatalogueConfig {
+    fn default() -> Self {
+        CatalogueConfig {
+            ttl_secs: 6 * 60 * 60,
+            refresh_secs: 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    /// Merges entities sharing an ISBN unless a request says otherwise.
+    pub merge: bool,
+    /// How long results are replayed to identical searches. Disabled when
+    /// zero.
+    pub cache_ttl_secs: u64,
+    /// Maximum number of searches whose results are kept.
+    pub cache_size: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            merge: false,
+            cache_ttl_secs: 0,
+            cache_size: 1_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResolverConfig {
+    /// Prefix of library ids served by this resolver, e.g. `seoul-seocho`.
+    pub id: String,
+    /// Region name used when geocoding branch libraries, e.g. `서울시 서초구`.
+    pub region: String,
+    #[serde(default)]
+    pub policy: RequestPolicy,
+    #[serde(default)]
+    pub breaker: BreakerConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// Flattening keeps `deny_unknown_fields` off this struct, so each
+    /// platform denies them instead, rejecting keys neither side knows.
+    #[serde(flatten)]
+    pub platform: Platform,
+}
+
+/// HTTP client settings for one library system.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Defaults to `heekkr-resolver-json-rs/<version>`.
+    pub user_agent: Option<String>,
+    /// Proxy for all requests, e.g. `http://proxy.internal:3128`.
+    pub proxy: Option<Url>,
+    /// PEM files with CA certificates trusted on top of the system roots.
+    /// Relative paths are resolved against the config file's directory.
+    pub ca_certs: Vec<PathBuf>,
+    /// Server certificates to accept regardless of their chain. When set,
+    /// no other certificate is accepted.
+    pub pinned_sha256: Vec<Fingerprint>,
+}
+
+/// When to stop sending requests to a library system that keeps failing.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BreakerConfig {
+    /// Consecutive failed searches or library loads that open the circuit.
+    pub failure_threshold: u32,
+    /// How long an open circuit rejects requests before letting one through.
+    pub cooldown_ms: u64,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            failure_threshold: 5,
+            cooldown_ms: 30_000,
+        }
+    }
+}
+
+/// Timeouts and retries for requests to one library system.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestPolicy {
+    /// Budget for loading the library list, including retries.
+    pub libraries_timeout_ms: u64,
+    /// Budget for a search across all of its pages, including retries.
+    pub search_timeout_ms: u64,
+    /// Limit for a single HTTP attempt. Unlimited when absent.
+    pub request_timeout_ms: Option<u64>,
+    /// Extra attempts after a connection error, timeout or 5xx/429 response.
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each further one.
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        RequestPolicy {
+            libraries_timeout_ms: 5_000,
+            search_timeout_ms: 15_000,
+            request_timeout_ms: None,
+            retries: 0,
+            backoff_ms: 200,
+            max_backoff_ms: 2_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Platform {
+    Eco(EcoPlatform),
+    Json(Box<JsonPlatform>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EcoPlatform {
+    pub host: Url,
+    /// Number of books requested per page.
+    #[serde(default = "default_page_size")]
+    pub page_size: u32,
+    /// Maximum number of pages fetched for a single search.
+    #[serde(default = "default_max_pages")]
+    pub max_pages: u32,
+    /// Fetches book details to fill descriptions. Disabled when absent.
+    pub detail: Option<EcoDetail>,
+}
+
+/// `path` may contain the placeholders `{pub_form_code}`, `{book_key}`,
+/// `{species_key}` and `{isbn}`; `description` selects the text from the
+/// JSON response.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EcoDetail {
+    pub path: String,
+    pub description: JsonPath,
+}
+
+fn default_page_size() -> u32 {
+    20
+}
+
+fn default_max_pages() -> u32 {
+    5
+}
+
+/// Describes a library system speaking arbitrary JSON. Paths inside
+/// `libraries` and `search.fields` are relative to each matched list item.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonPlatform {
+    pub host: Url,
+    pub libraries: JsonLibraries,
+    pub search: JsonSearch,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonLibraries {
+    pub path: String,
+    pub list: JsonPath,
+    pub id: JsonPath,
+    pub name: JsonPath,
+    pub latitude: Option<JsonPath>,
+    pub longitude: Option<JsonPath>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpMethod {
+    #[default]
+    Get,
+    Post,
+}
+
+/// Request and response mapping for searches.
+///
+/// `query` values and string leaves of `body` may contain the placeholders
+/// `{keyword}`, `{isbn}`, `{title}`, `{author}`, `{publisher}`, `{year_from}`,
+/// `{year_to}` and `{library_codes}`. Fields without a placeholder are folded
+/// into `{keyword}`, and query parameters left empty are omitted. A `body`
+/// string consisting solely of `{library_codes}` is replaced by an array of
+/// codes; elsewhere the codes are joined with commas.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonSearch {
+    #[serde(default)]
+    pub method: HttpMethod,
+    pub path: String,
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+    pub body: Option<serde_json::Value>,
+    pub list: JsonPath,
+    pub fields: JsonFields,
+    #[serde(default)]
+    pub status: JsonStatus,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonFields {
+    pub title: JsonPath,
+    pub library_code: JsonPath,
+    pub isbn: Option<JsonPath>,
+    pub author: Option<JsonPath>,
+    pub publisher: Option<JsonPath>,
+    pub description: Option<JsonPath>,
+    pub location: Option<JsonPath>,
+    pub call_number: Option<JsonPath>,
+    pub loan_status: Option<JsonPath>,
+    pub due_date: Option<JsonPath>,
+    pub requests: Option<JsonPath>,
+    /// Detail page, resolved against `host` when relative.
+    pub url: Option<JsonPath>,
+}
+
+/// Maps `loan_status` values onto holding states. Values matching neither
+/// list are reported as unavailable.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonStatus {
+    #[serde(default)]
+    pub available: Vec<String>,
+    #[serde(default)]
+    pub on_loan: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum ConfigErrors {
+    ReadError { path: String, msg: String },
+    ParseError { path: String, msg: String },
+    InvalidId { id: String },
+    DuplicateId { id: String },
+    ClientError { id: String, msg: String },
+    InvalidUrl { id: String, msg: String },
+    GeocodeCacheError { msg: String },
+    OverridesError { path: String, msg: String },
+    GeocodeProviderError { msg: String },
+}
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigErrors::ReadError { path, msg } => {
+                write!(f, "cannot read config {}: {}", path, msg)
+            }
+            ConfigErrors::ParseError { path, msg } => {
+                write!(f, "invalid config {}: {}", path, msg)
+            }
+            ConfigErrors::InvalidId { id } => {
+                write!(
+                    f,
+                    "invalid resolver id {:?}: must be non-empty without ':'",
+                    id
+                )
+            }
+            ConfigErrors::DuplicateId { id } => {
+                write!(f, "resolver id {:?} is declared more than once", id)
+            }
+            ConfigErrors::ClientError { id, msg } => {
+                write!(f, "cannot set up HTTP client of {:?}: {}", id, msg)
+            }
+            ConfigErrors::InvalidUrl { id, msg } => {
+                write!(f, "invalid URL of {:?}: {}", id, msg)
+            }
+            ConfigErrors::GeocodeCacheError { msg } => {
+                write!(f, "cannot set up geocoding cache: {}", msg)
+            }
+            ConfigErrors::OverridesError { path, msg } => {
+                write!(f, "invalid coordinate overrides {}: {}", path, msg)
+            }
+            ConfigErrors::GeocodeProviderError { msg } => {
+                write!(f, "cannot set up geocoding provider: {}", msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigErrors> {
+        let content = fs::read_to_string(path).map_err(|err| ConfigErrors::ReadError {
+            path: path.display().to_string(),
+            msg: err.to_string(),
+        })?;
+        let mut config: Config =
+            toml::from_str(&content).map_err(|err| ConfigErrors::ParseError {
+                path: path.display().to_string(),
+                msg: err.to_string(),
+            })?;
+        config.validate()?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for resolver in &mut config.resolvers {
+            for cert in &mut resolver.http.ca_certs {
+                *cert = dir.join(&*cert);
+            }
+        }
+        if let GeocodeCacheBackend::Disk { path } = &mut config.geocode.cache.backend {
+            *path = dir.join(&*path);
+        }
+        if let Some(path) = &mut config.geocode.overrides {
+            *path = dir.join(&*path);
+        }
+        for provider in &mut config.geocode.providers {
+            if let GeocodeProvider::Gazetteer { path } = provider {
+                *path = dir.join(&*path);
+            }
+        }
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut ids = HashSet::new();
+        for resolver in &self.resolvers {
+            if resolver.id.is_empty() || resolver.id.contains(':') {
+                return Err(ConfigErrors::InvalidId {
+                    id: resolver.id.clone(),
+                });
+            }
+            if !ids.insert(&resolver.id) {
+                return Err(ConfigErrors::DuplicateId {
+                    id: resolver.id.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECO: &str = r#"
+        [[resolver]]
+        id = "eco"
+        kind = "eco"
+        region = "서울시"
+        host = "http://127.0.0.1/"
+    "#;
+
+    const JSON: &str = r#"
+        [[resolver]]
+        id = "json"
+        kind = "json"
+        region = "서울시"
+        host = "http://127.0.0.1/"
+        libraries = { path = "libraries", list = "$[*]", id = "$.id", name = "$.name" }
+        search = { path = "search", list = "$[*]", fields = { title = "$.t", library_code = "$.l" } }
+    "#;
+
+    fn parse(content: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    #[test]
+    fn parses_resolvers() {
+        let config = parse(&format!("{ECO}max_pages = 2\n{JSON}")).unwrap();
+        assert_eq!(config.resolvers.len(), 2);
+        assert!(matches!(
+            &config.resolvers[0].platform,
+            Platform::Eco(EcoPlatform { max_pages: 2, .. })
+        ));
+        assert!(matches!(&config.resolvers[1].platform, Platform::Json(_)));
+    }
+
+    #[test]
+    fn rejects_unknown_resolver_fields() {
+        for field in ["hots = \"x\"", "max_page = 3", "polcy = {}"] {
+            let err = parse(&format!("{ECO}{field}\n")).unwrap_err();
+            assert!(err.message().contains("unknown field"), "{field}: {err}");
+            let err = parse(&format!("{JSON}{field}\n")).unwrap_err();
+            assert!(err.message().contains("unknown field"), "{field}: {err}");
+        }
+        let err = parse(&format!(
+            "{ECO}detail = {{ path = \"x\", descripton = \"$\" }}\n"
+        ));
+        assert!(err.is_err());
+    }
+}
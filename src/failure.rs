@@ -0,0 +1,96 @@
+use std::fmt;
+
+use tonic::{
+    metadata::{MetadataMap, MetadataValue},
+    Code, Status,
+};
+
+/// Metadata key listing failures as comma-separated `id=kind` pairs, where
+/// `id` is a library id for searches and a resolver id for library lists,
+/// including those a search near a location could not load.
+pub const FAILURES_KEY: &str = "x-heekkr-failures";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    Timeout,
+    Unreachable,
+    Parse,
+    Error,
+}
+
+impl FailureKind {
+    /// Resolvers report unreachable upstreams as `UNAVAILABLE` and
+    /// unparseable responses as `DATA_LOSS`.
+    pub fn of(status: &Status) -> FailureKind {
+        match status.code() {
+            Code::DeadlineExceeded => FailureKind::Timeout,
+            Code::Unavailable => FailureKind::Unreachable,
+            Code::DataLoss => FailureKind::Parse,
+            _ => FailureKind::Error,
+        }
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureKind::Timeout => "timeout",
+            FailureKind::Unreachable => "unreachable",
+            FailureKind::Parse => "parse",
+            FailureKind::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub id: String,
+    pub kind: FailureKind,
+}
+
+impl Failure {
+    pub fn new(id: &str, status: &Status) -> Failure {
+        Failure {
+            id: id.to_owned(),
+            kind: FailureKind::of(status),
+        }
+    }
+}
+
+pub fn insert_metadata(metadata: &mut MetadataMap, failures: &[Failure]) {
+    if failures.is_empty() {
+        return;
+    }
+    let value = failures
+        .iter()
+        .map(|f| format!("{}={}", f.id, f.kind))
+        .collect::<Vec<_>>()
+        .join(",");
+    if let Ok(value) = MetadataValue::try_from(value) {
+        metadata.insert(FAILURES_KEY, value);
+    }
+}
+
+/// A successful status ending a search stream, carrying failures as
+/// trailing metadata. Yields first: tonic drops responses encoded in the
+/// same poll as a status, so pages ready along with it would be lost.
+pub async fn trailer(failures: &[Failure]) -> Status {
+    tokio::task::yield_now().await;
+    let mut metadata = MetadataMap::new();
+    insert_metadata(&mut metadata, failures);
+    Status::with_metadata(Code::Ok, "", metadata)
+}
+
+/// Reads `(id, kind)` pairs written by [`insert_metadata`].
+pub fn from_metadata(metadata: &MetadataMap) -> Vec<(String, String)> {
+    metadata
+        .get(FAILURES_KEY)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            v.split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(id, kind)| (id.to_owned(), kind.to_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,74 @@
+//! The standard `grpc.health.v1` service, for load balancers and probes,
+//! served by `tonic-health` with statuses pushed as they change.
+//!
+//! The server as a whole, under the empty service name and the names of the
+//! services it hosts, is serving once the catalogue holds any library list.
+//! `resolver/<id>` reports whether the circuit of that resolver is closed.
+
+use heekkr::kr::heek::resolver_server::ResolverServer;
+use tonic_health::{server::HealthReporter, ServingStatus};
+
+use crate::{
+    breaker::Breakers,
+    catalogue::Catalogue,
+    proto::{diagnostics_server::DiagnosticsServer, libraries_server::LibrariesServer},
+    Diagnostics, JsonResolver,
+};
+
+/// Reports the server not serving until the catalogue is loaded, and each
+/// resolver by its circuit, in tasks that follow them from then on.
+pub async fn report(mut reporter: HealthReporter, breakers: &Breakers, catalogue: &Catalogue) {
+    set_server_serving(&mut reporter, false).await;
+    let mut loaded = catalogue.loaded();
+    let mut server = reporter.clone();
+    tokio::spawn(async move {
+        if loaded.wait_for(|loaded| *loaded).await.is_ok() {
+            set_server_serving(&mut server, true).await;
+        }
+    });
+
+    for (id, breaker) in breakers.iter() {
+        let service = format!("resolver/{id}");
+        let mut closed = breaker.closed();
+        set_status(&mut reporter, &service, *closed.borrow_and_update()).await;
+        let mut reporter = reporter.clone();
+        tokio::spawn(async move {
+            while closed.changed().await.is_ok() {
+                let serving = *closed.borrow_and_update();
+                set_status(&mut reporter, &service, serving).await;
+            }
+        });
+    }
+}
+
+async fn set_server_serving(reporter: &mut HealthReporter, serving: bool) {
+    set_status(reporter, "", serving).await;
+    if serving {
+        reporter.set_serving::<ResolverServer<JsonResolver>>().await;
+        reporter
+            .set_serving::<LibrariesServer<JsonResolver>>()
+            .await;
+        reporter
+            .set_serving::<DiagnosticsServer<Diagnostics>>()
+            .await;
+    } else {
+        reporter
+            .set_not_serving::<ResolverServer<JsonResolver>>()
+            .await;
+        reporter
+            .set_not_serving::<LibrariesServer<JsonResolver>>()
+            .await;
+        reporter
+            .set_not_serving::<DiagnosticsServer<Diagnostics>>()
+            .await;
+    }
+}
+
+async fn set_status(reporter: &mut HealthReporter, service: &str, serving: bool) {
+    let status = if serving {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::NotServing
+    };
+    reporter.set_service_status(service, status).await;
+}
@@ -0,0 +1,148 @@
+/// Finds the ISBN-13 of the item in values such as `978-89-364-3412-0`,
+/// `8936434128`, `9788936434120 03810` or
+/// `9788936434120 (세트) 9788936434137`.
+///
+/// Korean additional codes (부가기호) are stripped, and ISBNs followed by a
+/// set marker are used only when the item has no ISBN of its own. The shared
+/// `Book` message has no fields for either, so they are not kept. Returns
+/// `None` when no ISBN with a valid check digit is found.
+pub fn parse(raw: &str) -> Option<String> {
+    let tokens = raw
+        .split(|c: char| c.is_whitespace() || "()[]{},;/:".contains(c))
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut isbns: Vec<(String, bool)> = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        let is_set = tokens.get(i + 1).is_some_and(|next| is_set_marker(next));
+        let compact = token.replace('-', "").to_uppercase();
+        let isbn = match compact.len() {
+            18 => &compact[..13],
+            15 => &compact[..10],
+            _ => compact.as_str(),
+        };
+        if let Some(isbn13) = to_isbn13(isbn) {
+            isbns.push((isbn13, is_set));
+        }
+    }
+
+    isbns
+        .iter()
+        .find(|(_, set)| !*set)
+        .or(isbns.first())
+        .map(|(i, _)| i.clone())
+}
+
+/// Normalizes to a bare ISBN-13, passing `raw` through trimmed if it holds
+/// no valid ISBN.
+pub fn normalize(raw: &str) -> String {
+    parse(raw).unwrap_or_else(|| raw.trim().to_owned())
+}
+
+fn is_set_marker(token: &str) -> bool {
+    token == "세트" || token.eq_ignore_ascii_case("set")
+}
+
+fn to_isbn13(value: &str) -> Option<String> {
+    let digits = value
+        .chars()
+        .map(|c| match c {
+            'X' => Some(10),
+            c => c.to_digit(10),
+        })
+        .collect::<Option<Vec<u32>>>()?;
+
+    match digits.len() {
+        13 if !digits.contains(&10) => {
+            let sum: u32 = digits
+                .iter()
+                .enumerate()
+                .map(|(i, d)| if i % 2 == 0 { *d } else { d * 3 })
+                .sum();
+            sum.is_multiple_of(10).then(|| value.to_owned())
+        }
+        10 if !digits[..9].contains(&10) => {
+            let sum: u32 = digits
+                .iter()
+                .enumerate()
+                .map(|(i, d)| (10 - i as u32) * d)
+                .sum();
+            if !sum.is_multiple_of(11) {
+                return None;
+            }
+            let body = format!("978{}", &value[..9]);
+            let sum: u32 = body
+                .chars()
+                .enumerate()
+                .map(|(i, c)| c.to_digit(10).unwrap() * if i % 2 == 0 { 1 } else { 3 })
+                .sum();
+            Some(format!("{}{}", body, (10 - sum % 10) % 10))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_check_digits() {
+        assert_eq!(parse("9788936434120").as_deref(), Some("9788936434120"));
+        assert_eq!(parse("8936434128").as_deref(), Some("9788936434120"));
+    }
+
+    #[test]
+    fn rejects_invalid_check_digits() {
+        assert_eq!(parse("9788936434121"), None);
+        assert_eq!(parse("8936434127"), None);
+        assert_eq!(parse("12345"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn converts_isbn10_with_x_check_digit() {
+        assert_eq!(parse("080442957X").as_deref(), Some("9780804429573"));
+        assert_eq!(parse("080442957x").as_deref(), Some("9780804429573"));
+        // X is only valid as an ISBN-10 check digit.
+        assert_eq!(parse("08044295X7"), None);
+    }
+
+    #[test]
+    fn strips_hyphens() {
+        assert_eq!(parse("978-89-364-3412-0").as_deref(), Some("9788936434120"));
+        assert_eq!(parse("89-364-3412-8").as_deref(), Some("9788936434120"));
+    }
+
+    #[test]
+    fn strips_additional_codes() {
+        assert_eq!(
+            parse("9788936434120 03810").as_deref(),
+            Some("9788936434120")
+        );
+        assert_eq!(
+            parse("978893643412003810").as_deref(),
+            Some("9788936434120")
+        );
+        assert_eq!(parse("893643412803810").as_deref(), Some("9788936434120"));
+    }
+
+    #[test]
+    fn prefers_the_item_over_its_set() {
+        assert_eq!(
+            parse("9788936434137 (세트) 9788936434120").as_deref(),
+            Some("9788936434120")
+        );
+        assert_eq!(
+            parse("9788936434137 (set)").as_deref(),
+            Some("9788936434137")
+        );
+    }
+
+    #[test]
+    fn normalize_passes_invalid_values_through() {
+        assert_eq!(normalize(" 978-89-364-3412-0 "), "9788936434120");
+        assert_eq!(normalize(" 없음 "), "없음");
+        assert_eq!(normalize(""), "");
+    }
+}
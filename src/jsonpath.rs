@@ -0,0 +1,185 @@
+use std::{fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+
+/// A small subset of JSONPath: `$`, `.name`, `['name']`, `[0]` and `[*]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Field(String),
+    Index(usize),
+    Wildcard,
+}
+
+#[derive(Debug)]
+pub struct JsonPathError {
+    path: String,
+    msg: &'static str,
+}
+
+impl fmt::Display for JsonPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid json path {:?}: {}", self.path, self.msg)
+    }
+}
+
+impl std::error::Error for JsonPathError {}
+
+impl FromStr for JsonPath {
+    type Err = JsonPathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = |msg| JsonPathError {
+            path: s.to_owned(),
+            msg,
+        };
+
+        let mut rest = s
+            .strip_prefix('$')
+            .ok_or_else(|| err("must start with '$'"))?;
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(r) = rest.strip_prefix('.') {
+                let end = r.find(['.', '[']).unwrap_or(r.len());
+                let (name, r) = r.split_at(end);
+                if name.is_empty() {
+                    return Err(err("empty field name"));
+                }
+                segments.push(if name == "*" {
+                    Segment::Wildcard
+                } else {
+                    Segment::Field(name.to_owned())
+                });
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix('[') {
+                let end = r.find(']').ok_or_else(|| err("unclosed '['"))?;
+                let (inner, r) = r.split_at(end);
+                segments.push(if inner == "*" {
+                    Segment::Wildcard
+                } else if let Some(name) =
+                    inner.strip_prefix('\'').and_then(|n| n.strip_suffix('\''))
+                {
+                    Segment::Field(name.to_owned())
+                } else {
+                    Segment::Index(inner.parse().map_err(|_| err("invalid index"))?)
+                });
+                rest = &r[1..];
+            } else {
+                return Err(err("expected '.' or '['"));
+            }
+        }
+        Ok(JsonPath { segments })
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl JsonPath {
+    /// Returns every value matched by this path.
+    pub fn select<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        let mut current = vec![value];
+        for segment in &self.segments {
+            current = current
+                .into_iter()
+                .flat_map(|v| -> Vec<&Value> {
+                    match (segment, v) {
+                        (Segment::Field(name), Value::Object(map)) => {
+                            map.get(name).into_iter().collect()
+                        }
+                        (Segment::Index(i), Value::Array(items)) => {
+                            items.get(*i).into_iter().collect()
+                        }
+                        (Segment::Wildcard, Value::Array(items)) => items.iter().collect(),
+                        (Segment::Wildcard, Value::Object(map)) => map.values().collect(),
+                        _ => vec![],
+                    }
+                })
+                .collect();
+        }
+        current
+    }
+
+    /// Returns the first matched value as a string, if it is a non-empty
+    /// string, number or boolean.
+    pub fn text(&self, value: &Value) -> Option<String> {
+        self.select(value).into_iter().find_map(|v| match v {
+            Value::String(s) if !s.is_empty() => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            Value::Bool(b) => Some(b.to_string()),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn path(s: &str) -> JsonPath {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_segments() {
+        assert_eq!(path("$").segments, vec![]);
+        assert_eq!(
+            path("$.data['items'][2][*].name.*").segments,
+            vec![
+                Segment::Field("data".to_owned()),
+                Segment::Field("items".to_owned()),
+                Segment::Index(2),
+                Segment::Wildcard,
+                Segment::Field("name".to_owned()),
+                Segment::Wildcard,
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        for s in ["", "data", "$.", "$..a", "$[0", "$[x]", "$a"] {
+            assert!(s.parse::<JsonPath>().is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn selects_nested_keys_and_indices() {
+        let value = json!({"a": {"b": [{"c": 1}, {"c": 2}]}});
+        assert_eq!(path("$.a.b[1].c").select(&value), vec![&json!(2)]);
+        assert_eq!(
+            path("$.a.b[*].c").select(&value),
+            vec![&json!(1), &json!(2)]
+        );
+        assert_eq!(path("$").select(&value), vec![&value]);
+    }
+
+    #[test]
+    fn selects_nothing_for_missing_paths() {
+        let value = json!({"a": [1], "s": "x"});
+        assert!(path("$.b").select(&value).is_empty());
+        assert!(path("$.a[1]").select(&value).is_empty());
+        assert!(path("$.s.t").select(&value).is_empty());
+        assert!(path("$.a.b").select(&value).is_empty());
+    }
+
+    #[test]
+    fn text_skips_empty_strings_and_containers() {
+        let value = json!({"items": ["", {"x": 1}, 3, true]});
+        assert_eq!(path("$.items[*]").text(&value), Some("3".to_owned()));
+        assert_eq!(path("$.items[3]").text(&value), Some("true".to_owned()));
+        assert_eq!(path("$.items[0]").text(&value), None);
+        assert_eq!(path("$.missing").text(&value), None);
+    }
+}
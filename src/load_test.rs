@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use futures::{stream, StreamExt};
+use heekkr::kr::heek::{resolver_client::ResolverClient, GetLibrariesRequest, SearchRequest};
+use tokio::{sync::oneshot, time::sleep};
+use tonic::transport::Channel;
+
+/// Fires `requests` Search RPCs, `concurrency` at a time, against a running
+/// server while probing it with GetLibraries calls. A server whose runtime is
+/// starved shows up as probe latencies growing with the search load.
+pub async fn run(
+    endpoint: String,
+    term: String,
+    library_ids: Vec<String>,
+    concurrency: usize,
+    requests: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = ResolverClient::connect(endpoint).await?;
+
+    let (stop, stopped) = oneshot::channel();
+    let probe = tokio::spawn(probe(client.clone(), stopped));
+    let started = Instant::now();
+    let results = stream::iter(0..requests)
+        .map(|_| search(client.clone(), term.clone(), library_ids.clone()))
+        .buffer_unordered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+    let elapsed = started.elapsed();
+    let _ = stop.send(());
+    let mut probes = probe.await?;
+
+    let mut latencies = results
+        .iter()
+        .filter_map(|r| r.as_ref().ok().copied())
+        .collect::<Vec<_>>();
+    let failures = results.len() - latencies.len();
+    println!(
+        "{} searches ({} failed) in {:.2?}, {:.1} req/s",
+        requests,
+        failures,
+        elapsed,
+        requests as f64 / elapsed.as_secs_f64()
+    );
+    report("search", &mut latencies);
+    report("probe", &mut probes);
+    Ok(())
+}
+
+async fn search(
+    mut client: ResolverClient<Channel>,
+    term: String,
+    library_ids: Vec<String>,
+) -> Result<Duration, tonic::Status> {
+    let started = Instant::now();
+    let mut stream = client
+        .search(SearchRequest { library_ids, term })
+        .await?
+        .into_inner();
+    while stream.message().await?.is_some() {}
+    Ok(started.elapsed())
+}
+
+async fn probe(
+    mut client: ResolverClient<Channel>,
+    mut stopped: oneshot::Receiver<()>,
+) -> Vec<Duration> {
+    let mut latencies = vec![];
+    loop {
+        let started = Instant::now();
+        tokio::select! {
+            res = client.get_libraries(GetLibrariesRequest {}) => {
+                if res.is_ok() {
+                    latencies.push(started.elapsed());
+                }
+            }
+            _ = &mut stopped => break,
+        }
+        tokio::select! {
+            _ = sleep(Duration::from_millis(100)) => {}
+            _ = &mut stopped => break,
+        }
+    }
+    latencies
+}
+
+fn report(name: &str, latencies: &mut [Duration]) {
+    if latencies.is_empty() {
+        println!("{name}: no samples");
+        return;
+    }
+    latencies.sort();
+    let at = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    println!(
+        "{name}: p50 {:.2?}, p95 {:.2?}, max {:.2?} ({} samples)",
+        at(50),
+        at(95),
+        at(100),
+        latencies.len()
+    );
+}
@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use cached::{Cached, TimedCache};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::Address;
+use crate::config::{ConfigErrors, GeocodeCacheBackend, GeocodeCacheConfig};
+
+/// Bumped when persisted entries must be geocoded again. Version 1 stores
+/// coordinates as f64; earlier entries were rounded to f32.
+const FORMAT_VERSION: u32 = 1;
+
+/// Geocoding results by search keyword.
+#[tonic::async_trait]
+pub trait GeocodeCache: Send + Sync {
+    async fn get(&self, keyword: &str) -> Option<Address>;
+    async fn set(&self, keyword: &str, address: Address);
+}
+
+pub fn from_config(config: &GeocodeCacheConfig) -> Result<Box<dyn GeocodeCache>, ConfigErrors> {
+    let ttl = Duration::from_secs(config.ttl_secs);
+    Ok(match &config.backend {
+        GeocodeCacheBackend::Memory => Box::new(MemoryCache::new(ttl)),
+        GeocodeCacheBackend::Disk { path } => Box::new(DiskCache::new(path.clone(), ttl)?),
+        #[cfg(feature = "gcs")]
+        GeocodeCacheBackend::Gcs { prefix } => Box::new(gcs::Gcs::new(prefix, ttl)),
+        #[cfg(not(feature = "gcs"))]
+        GeocodeCacheBackend::Gcs { .. } => {
+            return Err(ConfigErrors::GeocodeCacheError {
+                msg: "built without the `gcs` feature".to_owned(),
+            })
+        }
+    })
+}
+
+pub struct MemoryCache {
+    entries: Mutex<TimedCache<String, Address>>,
+}
+
+impl MemoryCache {
+    pub fn new(ttl: Duration) -> MemoryCache {
+        MemoryCache {
+            entries: Mutex::new(TimedCache::with_lifespan(ttl.as_secs())),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl GeocodeCache for MemoryCache {
+    async fn get(&self, keyword: &str) -> Option<Address> {
+        self.entries
+            .lock()
+            .unwrap()
+            .cache_get(&keyword.to_owned())
+            .cloned()
+    }
+
+    async fn set(&self, keyword: &str, address: Address) {
+        self.entries
+            .lock()
+            .unwrap()
+            .cache_set(keyword.to_owned(), address);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct DiskEntry {
+    address: Address,
+    /// Seconds since the Unix epoch.
+    expires_at: u64,
+    /// Missing from entries written before `FORMAT_VERSION` existed.
+    #[serde(default)]
+    version: u32,
+}
+
+/// Keeps every entry in memory and rewrites the whole file on each change,
+/// which suits the few hundred libraries geocoded per deployment.
+pub struct DiskCache {
+    path: PathBuf,
+    ttl: Duration,
+    entries: tokio::sync::Mutex<HashMap<String, DiskEntry>>,
+}
+
+impl DiskCache {
+    pub fn new(path: PathBuf, ttl: Duration) -> Result<DiskCache, ConfigErrors> {
+        let err = |msg: String| ConfigErrors::GeocodeCacheError {
+            msg: format!("{}: {}", path.display(), msg),
+        };
+        let mut entries: HashMap<String, DiskEntry> = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).map_err(|e| err(e.to_string()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(err(e.to_string())),
+        };
+        // Outdated entries are geocoded again and dropped from the file on
+        // the next write.
+        let loaded = entries.len();
+        entries.retain(|_, e| e.version == FORMAT_VERSION);
+        if entries.len() < loaded {
+            warn!(
+                "Dropped {} outdated entries from {}",
+                loaded - entries.len(),
+                path.display()
+            );
+        }
+        Ok(DiskCache {
+            path,
+            ttl,
+            entries: tokio::sync::Mutex::new(entries),
+        })
+    }
+
+    async fn write(&self, entries: &HashMap<String, DiskEntry>) -> std::io::Result<()> {
+        let content = serde_json::to_vec_pretty(entries)?;
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, content).await?;
+        tokio::fs::rename(&tmp, &self.path).await
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[tonic::async_trait]
+impl GeocodeCache for DiskCache {
+    async fn get(&self, keyword: &str) -> Option<Address> {
+        let entries = self.entries.lock().await;
+        entries
+            .get(keyword)
+            .filter(|e| e.expires_at > now())
+            .map(|e| e.address.clone())
+    }
+
+    async fn set(&self, keyword: &str, address: Address) {
+        let mut entries = self.entries.lock().await;
+        let now = now();
+        entries.retain(|_, e| e.expires_at > now);
+        entries.insert(
+            keyword.to_owned(),
+            DiskEntry {
+                address,
+                expires_at: now + self.ttl.as_secs(),
+                version: FORMAT_VERSION,
+            },
+        );
+        if let Err(err) = self.write(&entries).await {
+            warn!("Failed to write {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+#[cfg(feature = "gcs")]
+mod gcs {
+    use std::time::Duration;
+
+    use cached::IOCachedAsync;
+    use cached_store_gcs::GcsCache;
+    use log::warn;
+    use tokio::sync::OnceCell;
+
+    use super::{Address, GeocodeCache, FORMAT_VERSION};
+
+    /// Connects on first use; lookups go uncached if that fails. Keys carry
+    /// the format version, so objects of earlier versions are left to
+    /// expire.
+    pub struct Gcs {
+        prefix: String,
+        ttl: Duration,
+        cache: OnceCell<Option<GcsCache<String, Address>>>,
+    }
+
+    impl Gcs {
+        pub fn new(prefix: &str, ttl: Duration) -> Gcs {
+            Gcs {
+                prefix: prefix.to_owned(),
+                ttl,
+                cache: OnceCell::new(),
+            }
+        }
+
+        async fn cache(&self) -> Option<&GcsCache<String, Address>> {
+            self.cache
+                .get_or_init(|| async {
+                    GcsCache::new(self.ttl, &self.prefix)
+                        .await
+                        .map_err(|err| warn!("Failed to build GCS cache: {}", err))
+                        .ok()
+                })
+                .await
+                .as_ref()
+        }
+    }
+
+    #[tonic::async_trait]
+    impl GeocodeCache for Gcs {
+        async fn get(&self, keyword: &str) -> Option<Address> {
+            let cache = self.cache().await?;
+            match cache.cache_get(&key(keyword)).await {
+                Ok(address) => address,
+                Err(err) => {
+                    warn!("Failed to read GCS cache for {}: {}", keyword, err);
+                    None
+                }
+            }
+        }
+
+        async fn set(&self, keyword: &str, address: Address) {
+            let Some(cache) = self.cache().await else {
+                return;
+            };
+            if let Err(err) = cache.cache_set(key(keyword), address).await {
+                warn!("Failed to write GCS cache for {}: {}", keyword, err);
+            }
+        }
+    }
+
+    fn key(keyword: &str) -> String {
+        format!("v{}/{}", FORMAT_VERSION, keyword)
+    }
+}
@@ -0,0 +1,70 @@
+use std::{fs, path::Path};
+
+use serde::Deserialize;
+
+use super::{Address, LocationErrors, LocationService};
+use crate::config::ConfigErrors;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GazetteerFile {
+    #[serde(rename = "place", default)]
+    places: Vec<Place>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Place {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    road_address: Option<String>,
+    lot_address: Option<String>,
+    phone: Option<String>,
+}
+
+/// Places from a local TOML file, for running without any API key.
+///
+/// A place matches keywords equal to its name or ending with it after a
+/// space, so `반포도서관` matches `서울시 서초구 반포도서관`. The longest
+/// matching name wins.
+pub struct Gazetteer {
+    places: Vec<Place>,
+}
+
+impl Gazetteer {
+    pub fn load(path: &Path) -> Result<Gazetteer, ConfigErrors> {
+        let err = |msg: String| ConfigErrors::GeocodeProviderError {
+            msg: format!("{}: {}", path.display(), msg),
+        };
+        let content = fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+        let file: GazetteerFile = toml::from_str(&content).map_err(|e| err(e.to_string()))?;
+        Ok(Gazetteer {
+            places: file.places,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl LocationService for Gazetteer {
+    async fn search_keyword(&self, keyword: &str) -> Result<Address, LocationErrors> {
+        let keyword = keyword.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.places
+            .iter()
+            .filter(|p| keyword == p.name || keyword.ends_with(&format!(" {}", p.name)))
+            .max_by_key(|p| p.name.len())
+            .map(|p| Address {
+                x: p.longitude,
+                y: p.latitude,
+                place_name: Some(p.name.clone()),
+                road_address: p.road_address.clone(),
+                lot_address: p.lot_address.clone(),
+                phone: p.phone.clone(),
+            })
+            .ok_or(LocationErrors::NotFoundError)
+    }
+
+    fn cacheable(&self) -> bool {
+        false
+    }
+}
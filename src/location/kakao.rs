@@ -1,26 +1,38 @@
 use std::env;
-use std::time::Duration;
 
-use cached::proc_macro::io_cached;
-use cached_store_gcs::GcsCache;
 use log::warn;
 use reqwest::{
     header::{HeaderMap, HeaderValue},
-    Client,
+    Client, StatusCode,
 };
 use serde::Deserialize;
 
-use super::{Address, LocationErrors, LocationService};
+use super::{non_empty, Address, LocationErrors, LocationService};
 
 #[derive(Deserialize)]
 struct Response {
     documents: Vec<Document>,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorResponse {
+    error_type: String,
+    message: String,
+}
+
 #[derive(Deserialize)]
 struct Document {
     x: String,
     y: String,
+    #[serde(default)]
+    place_name: String,
+    #[serde(default)]
+    road_address_name: String,
+    #[serde(default)]
+    address_name: String,
+    #[serde(default)]
+    phone: String,
 }
 
 pub struct Kakao {
@@ -38,14 +50,14 @@ impl Kakao {
             "Authorization",
             HeaderValue::from_str(&format!("KakaoAK {}", key)).unwrap(),
         );
-        return Ok(Kakao {
+        Ok(Kakao {
             client: reqwest::Client::builder()
                 .default_headers(headers)
                 .build()
                 .map_err(|_| LocationErrors::CreateServiceError {
                     msg: "cannot create reqwest client".to_owned(),
                 })?,
-        });
+        })
     }
 }
 
@@ -56,26 +68,11 @@ impl LocationService for Kakao {
     }
 }
 
-#[io_cached(
-    map_error = r##"|_| LocationErrors::SearchError { msg: "cache error".to_owned() }"##,
-    type = "GcsCache<String, Address>",
-    create = r##" {
-        GcsCache::new(
-            Duration::from_secs(60 * 60 *  24 * 30),
-            "kakao-search-keyword/",
-        )
-        .await
-        .expect("error building gcs cache")
-    } "##,
-    convert = r#"{ keyword.to_owned() }"#
-)]
 async fn search_keyword(client: &Client, keyword: &str) -> Result<Address, LocationErrors> {
     let res = _search_keyword(client, keyword).await;
-    if let Err(err) = &res {
-        warn!(
-            "Failed to search location for keyword {}: {:?}",
-            keyword, err
-        );
+    match &res {
+        Err(LocationErrors::NotFoundError) | Ok(_) => {}
+        Err(err) => warn!("Failed to search location for keyword {}: {}", keyword, err),
     }
     res
 }
@@ -86,33 +83,47 @@ async fn _search_keyword(client: &Client, keyword: &str) -> Result<Address, Loca
         .query(&[("query", keyword), ("size", "1")])
         .send()
         .await
-        .map_err(|_| LocationErrors::SearchError {
-            msg: "search result error".to_owned(),
-        })?
-        .json::<Response>()
-        .await
-        .map_err(|_| LocationErrors::SearchError {
-            msg: "cannot deserialize response".to_owned(),
+        .map_err(|err| LocationErrors::NetworkError {
+            msg: err.to_string(),
         })?;
 
-    if let Some(document) = response.documents.first() {
-        Ok(Address {
-            x: document
-                .x
-                .parse()
-                .map_err(|_| LocationErrors::SearchError {
-                    msg: "float parse error".to_owned(),
-                })?,
-            y: document
-                .y
-                .parse()
-                .map_err(|_| LocationErrors::SearchError {
-                    msg: "float parse error".to_owned(),
-                })?,
-        })
-    } else {
-        Err(LocationErrors::SearchError {
-            msg: "no search result".to_owned(),
-        })
+    let status = response.status();
+    if !status.is_success() {
+        let msg = response
+            .json::<ErrorResponse>()
+            .await
+            .map(|e| format!("{}: {}", e.error_type, e.message))
+            .unwrap_or_else(|_| status.to_string());
+        return Err(match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => LocationErrors::AuthError { msg },
+            StatusCode::TOO_MANY_REQUESTS => LocationErrors::QuotaError { msg },
+            _ => LocationErrors::NetworkError { msg },
+        });
     }
+
+    let response =
+        response
+            .json::<Response>()
+            .await
+            .map_err(|err| LocationErrors::DecodeError {
+                msg: err.to_string(),
+            })?;
+    let document = response
+        .documents
+        .into_iter()
+        .next()
+        .ok_or(LocationErrors::NotFoundError)?;
+    let parse = |v: &str| {
+        v.parse().map_err(|_| LocationErrors::DecodeError {
+            msg: format!("invalid coordinate {:?}", v),
+        })
+    };
+    Ok(Address {
+        x: parse(&document.x)?,
+        y: parse(&document.y)?,
+        place_name: non_empty(document.place_name),
+        road_address: non_empty(document.road_address_name),
+        lot_address: non_empty(document.address_name),
+        phone: non_empty(document.phone),
+    })
 }
@@ -1,28 +1,243 @@
+use std::{fmt, sync::Mutex};
+
+use cached::{Cached, TimedCache, TimedSizedCache};
+use log::warn;
+
+use crate::config::{ConfigErrors, GeocodeConfig, GeocodeProvider};
+use cache::GeocodeCache;
+use gazetteer::Gazetteer;
 use kakao::Kakao;
+pub use overrides::Overrides;
+use vworld::VWorld;
 
+mod cache;
+mod gazetteer;
 mod kakao;
+mod overrides;
+mod vworld;
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// A geocoded place. Fields besides the coordinate are missing from
+/// entries cached before they were added.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct Address {
-    pub x: f32,
-    pub y: f32,
+    pub x: f64,
+    pub y: f64,
+    #[serde(default)]
+    pub place_name: Option<String>,
+    /// 도로명 주소
+    #[serde(default)]
+    pub road_address: Option<String>,
+    /// 지번 주소
+    #[serde(default)]
+    pub lot_address: Option<String>,
+    #[serde(default)]
+    pub phone: Option<String>,
+}
+
+/// Providers answer with empty strings for unknown fields.
+fn non_empty(value: String) -> Option<String> {
+    (!value.trim().is_empty()).then_some(value)
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum LocationErrors {
-    CreateServiceError { msg: String },
-    SearchError { msg: String },
+    CreateServiceError {
+        msg: String,
+    },
+    /// The API key is missing, invalid or not allowed to use the service.
+    AuthError {
+        msg: String,
+    },
+    /// The daily or per-second request quota is used up.
+    QuotaError {
+        msg: String,
+    },
+    /// The keyword matched no place.
+    NotFoundError,
+    /// The provider could not be reached or failed to answer.
+    NetworkError {
+        msg: String,
+    },
+    /// The provider answered with something unexpected.
+    DecodeError {
+        msg: String,
+    },
+}
+
+impl fmt::Display for LocationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocationErrors::CreateServiceError { msg } => {
+                write!(f, "cannot create location service: {}", msg)
+            }
+            LocationErrors::AuthError { msg } => write!(f, "not authorized: {}", msg),
+            LocationErrors::QuotaError { msg } => write!(f, "quota exceeded: {}", msg),
+            LocationErrors::NotFoundError => write!(f, "no search result"),
+            LocationErrors::NetworkError { msg } => write!(f, "request failed: {}", msg),
+            LocationErrors::DecodeError { msg } => write!(f, "invalid response: {}", msg),
+        }
+    }
 }
 
 #[tonic::async_trait]
-pub trait LocationService {
+pub trait LocationService: Send + Sync {
     async fn search_keyword(&self, keyword: &str) -> Result<Address, LocationErrors>;
+
+    /// Whether results are worth keeping in the geocoding cache. Local
+    /// sources answer faster than any cache.
+    fn cacheable(&self) -> bool {
+        true
+    }
+}
+
+/// Places looked up for clients kept in memory, and for how long.
+const PLACES_SIZE: usize = 1_000;
+const PLACES_TTL_SECS: u64 = 60 * 60;
+
+/// Locates libraries from the overrides file, or by looking up keywords with
+/// each configured provider in turn, remembering results in the configured
+/// cache and keywords nobody found for a shorter while.
+pub struct Geocoder {
+    providers: Vec<Box<dyn LocationService>>,
+    cache: Box<dyn GeocodeCache>,
+    not_found: Option<Mutex<TimedCache<String, ()>>>,
+    /// Results of `search_place`, found or not.
+    places: Mutex<TimedSizedCache<String, Option<Address>>>,
+    overrides: Overrides,
 }
 
-pub async fn search_keyword(keyword: &str) -> Option<Address> {
-    if let Ok(service) = Kakao::new() {
-        service.search_keyword(keyword).await.ok()
-    } else {
-        None
+impl Geocoder {
+    /// Providers missing an API key are left out of the chain.
+    pub fn new(config: &GeocodeConfig) -> Result<Geocoder, ConfigErrors> {
+        let mut providers: Vec<Box<dyn LocationService>> = vec![];
+        for provider in &config.providers {
+            let service: Result<Box<dyn LocationService>, LocationErrors> = match provider {
+                GeocodeProvider::Kakao => Kakao::new().map(|s| Box::new(s) as _),
+                GeocodeProvider::Vworld => VWorld::new().map(|s| Box::new(s) as _),
+                GeocodeProvider::Gazetteer { path } => Ok(Box::new(Gazetteer::load(path)?)),
+            };
+            match service {
+                Ok(service) => providers.push(service),
+                Err(err) => warn!("Skipping geocoding provider {:?}: {}", provider, err),
+            }
+        }
+        if providers.is_empty() {
+            warn!("Geocoding disabled: no provider available");
+        }
+        Ok(Geocoder {
+            providers,
+            cache: cache::from_config(&config.cache)?,
+            not_found: (config.cache.not_found_ttl_secs > 0)
+                .then(|| Mutex::new(TimedCache::with_lifespan(config.cache.not_found_ttl_secs))),
+            places: Mutex::new(TimedSizedCache::with_size_and_lifespan(
+                PLACES_SIZE,
+                PLACES_TTL_SECS,
+            )),
+            overrides: match &config.overrides {
+                Some(path) => Overrides::load(path)?,
+                None => Overrides::default(),
+            },
+        })
     }
+
+    /// The overridden location of `library_id`, or the first result for
+    /// `keyword`.
+    pub async fn locate(&self, library_id: &str, keyword: &str) -> Option<Address> {
+        match self.overridden(library_id) {
+            Some(address) => Some(address),
+            None => self.search_keyword(keyword).await,
+        }
+    }
+
+    /// The location of `library_id` from the overrides file, which wins
+    /// over any other source, including coordinates from upstream.
+    pub fn overridden(&self, library_id: &str) -> Option<Address> {
+        self.overrides.get(library_id).map(|o| Address {
+            x: o.longitude,
+            y: o.latitude,
+            road_address: o.address.clone(),
+            ..Default::default()
+        })
+    }
+
+    /// The first result for `keyword`.
+    pub async fn search_keyword(&self, keyword: &str) -> Option<Address> {
+        if let Some(address) = self.cache.get(keyword).await {
+            return Some(address);
+        }
+        if let Some(not_found) = &self.not_found {
+            if not_found
+                .lock()
+                .unwrap()
+                .cache_get(&keyword.to_owned())
+                .is_some()
+            {
+                return None;
+            }
+        }
+        match self.ask_providers(keyword).await {
+            Ok((address, cacheable)) => {
+                if cacheable {
+                    self.cache.set(keyword, address.clone()).await;
+                }
+                Some(address)
+            }
+            Err(all_not_found) => {
+                if all_not_found {
+                    if let Some(not_found) = &self.not_found {
+                        not_found.lock().unwrap().cache_set(keyword.to_owned(), ());
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Like `search_keyword` for keywords from clients, which are too many
+    /// and varied to persist. Results are only kept in a small in-memory
+    /// cache.
+    pub async fn search_place(&self, keyword: &str) -> Option<Address> {
+        let key = keyword.to_owned();
+        if let Some(address) = self.places.lock().unwrap().cache_get(&key) {
+            return address.clone();
+        }
+        if let Some(address) = self.cache.get(keyword).await {
+            return Some(address);
+        }
+        let address = match self.ask_providers(keyword).await {
+            Ok((address, _)) => Some(address),
+            Err(true) => None,
+            // Asked again next time.
+            Err(false) => return None,
+        };
+        self.places.lock().unwrap().cache_set(key, address.clone());
+        address
+    }
+
+    /// The first result for `keyword` and whether it may be cached, or
+    /// whether every provider found nothing. Only such a miss is worth
+    /// remembering; auth, quota and network failures may clear up by the
+    /// next lookup.
+    async fn ask_providers(&self, keyword: &str) -> Result<(Address, bool), bool> {
+        let mut all_not_found = !self.providers.is_empty();
+        for provider in &self.providers {
+            match provider.search_keyword(keyword).await {
+                Ok(address) => return Ok((address, provider.cacheable())),
+                Err(LocationErrors::NotFoundError) => {}
+                Err(_) => all_not_found = false,
+            }
+        }
+        Err(all_not_found)
+    }
+}
+
+/// Great-circle distance in metres between two latitude/longitude pairs.
+pub fn distance_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+    const EARTH_RADIUS_M: f64 = 6_371_000.0;
+    let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lng = (b.1 - a.1).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().asin()
 }
@@ -0,0 +1,55 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::config::ConfigErrors;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverridesFile {
+    #[serde(rename = "library", default)]
+    libraries: Vec<Override>,
+}
+
+/// Where a library really is, for branches geocoding gets wrong.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Override {
+    pub id: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub address: Option<String>,
+}
+
+/// Overrides by library id.
+#[derive(Debug, Default)]
+pub struct Overrides(HashMap<String, Override>);
+
+impl Overrides {
+    pub fn load(path: &Path) -> Result<Overrides, ConfigErrors> {
+        let err = |msg: String| ConfigErrors::OverridesError {
+            path: path.display().to_string(),
+            msg,
+        };
+        let content = fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+        let file: OverridesFile = toml::from_str(&content).map_err(|e| err(e.to_string()))?;
+        let mut overrides = HashMap::new();
+        for o in file.libraries {
+            if let Some(o) = overrides.insert(o.id.clone(), o) {
+                return Err(err(format!("library {:?} is overridden twice", o.id)));
+            }
+        }
+        Ok(Overrides(overrides))
+    }
+
+    pub fn get(&self, library_id: &str) -> Option<&Override> {
+        self.0.get(library_id)
+    }
+
+    /// Overrides sorted by library id.
+    pub fn iter(&self) -> Vec<&Override> {
+        let mut overrides = self.0.values().collect::<Vec<_>>();
+        overrides.sort_by(|a, b| a.id.cmp(&b.id));
+        overrides
+    }
+}
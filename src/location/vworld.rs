@@ -0,0 +1,154 @@
+use std::env;
+
+use log::warn;
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::{non_empty, Address, LocationErrors, LocationService};
+
+#[derive(Deserialize)]
+struct Response {
+    response: ResponseBody,
+}
+
+#[derive(Deserialize)]
+struct ResponseBody {
+    status: String,
+    result: Option<SearchResult>,
+    error: Option<ErrorBody>,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    code: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    #[serde(default)]
+    title: String,
+    point: Point,
+    #[serde(default)]
+    address: ItemAddress,
+}
+
+#[derive(Default, Deserialize)]
+struct ItemAddress {
+    #[serde(default)]
+    road: String,
+    #[serde(default)]
+    parcel: String,
+}
+
+#[derive(Deserialize)]
+struct Point {
+    x: String,
+    y: String,
+}
+
+/// Place search of the VWorld open API by the Ministry of Land.
+pub struct VWorld {
+    client: Client,
+    key: String,
+}
+
+impl VWorld {
+    pub fn new() -> Result<VWorld, LocationErrors> {
+        let key = env::var("VWORLD_API_KEY").map_err(|_| LocationErrors::CreateServiceError {
+            msg: "no api key".to_owned(),
+        })?;
+        Ok(VWorld {
+            client: Client::new(),
+            key,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl LocationService for VWorld {
+    async fn search_keyword(&self, keyword: &str) -> Result<Address, LocationErrors> {
+        let res = self._search_keyword(keyword).await;
+        match &res {
+            Err(LocationErrors::NotFoundError) | Ok(_) => {}
+            Err(err) => warn!("Failed to search location for keyword {}: {}", keyword, err),
+        }
+        res
+    }
+}
+
+impl VWorld {
+    async fn _search_keyword(&self, keyword: &str) -> Result<Address, LocationErrors> {
+        let response = self
+            .client
+            .get("https://api.vworld.kr/req/search")
+            .query(&[
+                ("service", "search"),
+                ("request", "search"),
+                ("version", "2.0"),
+                ("crs", "EPSG:4326"),
+                ("type", "place"),
+                ("size", "1"),
+                ("format", "json"),
+                ("errorformat", "json"),
+                ("query", keyword),
+                ("key", &self.key),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|err| LocationErrors::NetworkError {
+                msg: err.to_string(),
+            })?
+            .json::<Response>()
+            .await
+            .map_err(|err| LocationErrors::DecodeError {
+                msg: err.to_string(),
+            })?
+            .response;
+
+        match response.status.as_str() {
+            "OK" => {}
+            "NOT_FOUND" => return Err(LocationErrors::NotFoundError),
+            _ => return Err(classify(response.status, response.error)),
+        }
+        let item = response
+            .result
+            .and_then(|r| r.items.into_iter().next())
+            .ok_or(LocationErrors::NotFoundError)?;
+        let parse = |v: &str| {
+            v.parse().map_err(|_| LocationErrors::DecodeError {
+                msg: format!("invalid coordinate {:?}", v),
+            })
+        };
+        Ok(Address {
+            x: parse(&item.point.x)?,
+            y: parse(&item.point.y)?,
+            place_name: non_empty(item.title),
+            road_address: non_empty(item.address.road),
+            lot_address: non_empty(item.address.parcel),
+            phone: None,
+        })
+    }
+}
+
+/// VWorld reports failures in the body with a 200 status.
+fn classify(status: String, error: Option<ErrorBody>) -> LocationErrors {
+    let Some(error) = error else {
+        return LocationErrors::DecodeError {
+            msg: format!("status {}", status),
+        };
+    };
+    let msg = format!("{}: {}", error.code, error.text);
+    match error.code.as_str() {
+        "INVALID_KEY" | "INCORRECT_KEY" | "UNAVAILABLE_KEY" => LocationErrors::AuthError { msg },
+        "OVER_REQUEST_LIMIT" => LocationErrors::QuotaError { msg },
+        _ => LocationErrors::NetworkError { msg },
+    }
+}
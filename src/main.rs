@@ -1,22 +1,58 @@
-use std::{env, net::SocketAddr, pin::Pin};
+use std::{env, net::SocketAddr, path::PathBuf, pin::Pin, process, sync::Arc, time::Duration};
 
 use clap::{Parser, Subcommand};
 use heekkr::kr::heek::{
-    resolver_server, GetLibrariesRequest, GetLibrariesResponse, SearchRequest, SearchResponse,
+    resolver_server, GetLibrariesRequest, GetLibrariesResponse, LatLng, SearchRequest,
+    SearchResponse,
 };
 use tokio_stream::{Stream, StreamExt};
-use tonic::{transport::Server, Request, Response, Status};
+use tonic::{metadata::MetadataMap, transport::Server, Request, Response, Status};
 
-use search::{get_libraries, search};
+use breaker::{Breakers, State};
+use catalogue::Catalogue;
+use config::{CatalogueConfig, Config, SearchConfig};
+use location::{Geocoder, Overrides};
+use proto::{
+    diagnostics_client::DiagnosticsClient, diagnostics_server, libraries_server,
+    nearest_libraries_request::Origin, CircuitState, GetLibraryDetailsRequest,
+    GetLibraryDetailsResponse, GetStatusRequest, GetStatusResponse, NearestLibrariesRequest,
+    NearestLibrariesResponse, ResolverStatus,
+};
+use query::Query;
+use resolver::SharedResolver;
+use result_cache::ResultCache;
+use search::{get_libraries, refresh_libraries, search, SearchOptions};
 
 type SearchResponseStream = Pin<Box<dyn Stream<Item = Result<SearchResponse, Status>> + Send>>;
 
+mod breaker;
+mod catalogue;
+mod config;
+mod failure;
+mod health;
+mod isbn;
+mod jsonpath;
+mod load_test;
 mod location;
+mod nearest;
+mod proto;
+mod query;
 mod resolver;
+mod result_cache;
 mod search;
 
 #[derive(Parser)]
 struct Cli {
+    /// Path to the resolver registry
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "RESOLVER_CONFIG",
+        default_value = "resolvers.toml"
+    )]
+    config: PathBuf,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -28,25 +64,104 @@ enum Commands {
         address: SocketAddr,
     },
     Libraries,
+    /// List libraries nearest to a coordinate or place
+    Nearest {
+        /// Place name to geocode, unless `--lat` and `--lng` are given
+        #[arg(required_unless_present = "lat")]
+        keyword: Option<String>,
+        #[arg(long, requires = "lng", allow_negative_numbers = true)]
+        lat: Option<f64>,
+        #[arg(long, requires = "lat", allow_negative_numbers = true)]
+        lng: Option<f64>,
+        /// Maximum distance in metres
+        #[arg(long)]
+        radius: Option<f64>,
+        /// Maximum number of libraries
+        #[arg(short = 'n', long)]
+        count: Option<usize>,
+        /// Only libraries of these resolvers
+        #[arg(short, long)]
+        resolver: Vec<String>,
+    },
+    /// List overridden libraries whose geocoded location is far from the
+    /// override
+    CheckOverrides {
+        /// Minimum distance in metres to report
+        #[arg(long, default_value_t = 200.0)]
+        threshold: f64,
+    },
     Search {
+        /// Free text, optionally with `isbn:`, `title:`, `author:`,
+        /// `publisher:` or `year:` terms
+        #[arg(default_value = "")]
         keyword: String,
         #[arg(short, long)]
         library: Vec<String>,
+        #[arg(long)]
+        isbn: Option<String>,
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(long)]
+        author: Option<String>,
+        #[arg(long)]
+        publisher: Option<String>,
+        #[arg(long)]
+        year_from: Option<i32>,
+        #[arg(long)]
+        year_to: Option<i32>,
+        /// Merge books sharing an ISBN across libraries
+        #[arg(long)]
+        merge: bool,
+        /// Search libraries near `latitude,longitude`, ordering holdings by
+        /// distance
+        #[arg(long, value_parser = parse_coordinate, requires = "radius")]
+        near: Option<LatLng>,
+        /// Maximum distance in metres from `--near`
+        #[arg(long, requires = "near")]
+        radius: Option<f64>,
+    },
+    /// Fire concurrent Search RPCs at a running server and report latencies
+    LoadTest {
+        #[arg(default_value = "http://[::1]:50051")]
+        endpoint: String,
+        #[arg(short, long)]
+        term: String,
+        #[arg(short, long)]
+        library: Vec<String>,
+        #[arg(long, default_value_t = 64)]
+        concurrency: usize,
+        #[arg(long, default_value_t = 512)]
+        requests: usize,
+    },
+    /// Show the circuit breaker state of each resolver on a running server
+    Status {
+        #[arg(default_value = "http://[::1]:50051")]
+        endpoint: String,
     },
 }
 
-#[derive(Default)]
-pub struct JsonResolver {}
+pub struct JsonResolver {
+    resolvers: Arc<Vec<SharedResolver>>,
+    geocoder: Arc<Geocoder>,
+    breakers: Arc<Breakers>,
+    catalogue: Arc<Catalogue>,
+    results: Arc<ResultCache>,
+    search_config: SearchConfig,
+}
 
 #[tonic::async_trait]
 impl resolver_server::Resolver for JsonResolver {
     async fn get_libraries(
         &self,
-        _request: Request<GetLibrariesRequest>,
+        request: Request<GetLibrariesRequest>,
     ) -> Result<Response<GetLibrariesResponse>, Status> {
-        let libraries = get_libraries().await;
-        let reply = GetLibrariesResponse { libraries };
-        Ok(Response::new(reply))
+        let deadline = grpc_timeout(request.metadata()).and_then(search::deadline);
+        let (libraries, failures) =
+            get_libraries(&self.resolvers, &self.breakers, &self.catalogue, deadline).await;
+        let libraries = libraries.into_iter().filter_map(|d| d.library).collect();
+        let mut response = Response::new(GetLibrariesResponse { libraries });
+        failure::insert_metadata(response.metadata_mut(), &failures);
+        Ok(response)
     }
 
     type SearchStream = SearchResponseStream;
@@ -55,23 +170,317 @@ impl resolver_server::Resolver for JsonResolver {
         &self,
         request: Request<SearchRequest>,
     ) -> Result<Response<Self::SearchStream>, Status> {
-        let stream = search(&request.get_ref().term, &request.get_ref().library_ids).await;
-        Ok(Response::new(stream))
+        let query = Query::parse(&request.get_ref().term)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let near = near(request.metadata())?;
+        let mut library_ids = request.get_ref().library_ids.clone();
+        if near.as_ref().is_some_and(|(_, radius_m)| *radius_m <= 0.0) && library_ids.is_empty() {
+            return Err(Status::invalid_argument(
+                "Radius is required without library ids",
+            ));
+        }
+        let mut options = SearchOptions {
+            merge: request
+                .metadata()
+                .get("x-heekkr-merge")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(self.search_config.merge),
+            deadline: grpc_timeout(request.metadata()).and_then(search::deadline),
+            no_cache: request
+                .metadata()
+                .get("cache-control")
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.contains("no-cache")),
+            failures: vec![],
+        };
+        let distances = match &near {
+            Some((origin, radius_m)) => {
+                let (ids, distances, failures) = nearest::library_ids_near(
+                    &self.resolvers,
+                    &self.breakers,
+                    &self.catalogue,
+                    origin,
+                    *radius_m,
+                    &library_ids,
+                    options.deadline,
+                )
+                .await;
+                library_ids = ids;
+                options.failures = failures;
+                Some(distances)
+            }
+            None => None,
+        };
+        let stream = search(
+            &self.resolvers,
+            &self.breakers,
+            &self.results,
+            query,
+            &library_ids,
+            options,
+        )
+        .await;
+        Ok(Response::new(match distances {
+            Some(distances) => nearest::order_holdings(stream, distances),
+            None => stream,
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl libraries_server::Libraries for JsonResolver {
+    async fn get_library_details(
+        &self,
+        request: Request<GetLibraryDetailsRequest>,
+    ) -> Result<Response<GetLibraryDetailsResponse>, Status> {
+        let deadline = grpc_timeout(request.metadata()).and_then(search::deadline);
+        let (libraries, failures) =
+            get_libraries(&self.resolvers, &self.breakers, &self.catalogue, deadline).await;
+        let mut response = Response::new(GetLibraryDetailsResponse { libraries });
+        failure::insert_metadata(response.metadata_mut(), &failures);
+        Ok(response)
+    }
+
+    async fn nearest_libraries(
+        &self,
+        request: Request<NearestLibrariesRequest>,
+    ) -> Result<Response<NearestLibrariesResponse>, Status> {
+        let deadline = grpc_timeout(request.metadata()).and_then(search::deadline);
+        let request = request.into_inner();
+        if request.radius_m.is_nan() || request.radius_m < 0.0 {
+            return Err(Status::invalid_argument("Radius must not be negative"));
+        }
+        let resolvers = nearest::select(&self.resolvers, &request.resolver_ids)?;
+        let origin = nearest::locate(&self.geocoder, request.origin).await?;
+        let (libraries, failures) =
+            get_libraries(&resolvers, &self.breakers, &self.catalogue, deadline).await;
+        let libraries =
+            nearest::nearest(libraries, &origin, request.radius_m, request.count as usize);
+        let mut response = Response::new(NearestLibrariesResponse {
+            origin: Some(origin),
+            libraries,
+        });
+        failure::insert_metadata(response.metadata_mut(), &failures);
+        Ok(response)
+    }
+}
+
+pub struct Diagnostics {
+    resolvers: Arc<Vec<SharedResolver>>,
+    breakers: Arc<Breakers>,
+}
+
+#[tonic::async_trait]
+impl diagnostics_server::Diagnostics for Diagnostics {
+    async fn get_status(
+        &self,
+        _request: Request<GetStatusRequest>,
+    ) -> Result<Response<GetStatusResponse>, Status> {
+        let now = tokio::time::Instant::now();
+        let resolvers = self
+            .breakers
+            .iter()
+            .into_iter()
+            .map(|(id, breaker)| {
+                let (state, failures) = breaker.status();
+                let (circuit, until) = match state {
+                    State::Closed => (CircuitState::Closed, None),
+                    State::Open { until } => (CircuitState::Open, Some(until)),
+                    State::HalfOpen { until } => (CircuitState::HalfOpen, Some(until)),
+                };
+                ResolverStatus {
+                    id: id.clone(),
+                    circuit: circuit.into(),
+                    consecutive_failures: failures,
+                    retry_after_ms: until
+                        .map(|u| u.saturating_duration_since(now).as_millis() as u64)
+                        .unwrap_or_default(),
+                    retries: self
+                        .resolvers
+                        .iter()
+                        .find(|r| r.id() == *id)
+                        .map_or(0, |r| r.retries()),
+                }
+            })
+            .collect();
+        Ok(Response::new(GetStatusResponse { resolvers }))
+    }
+}
+
+fn parse_coordinate(value: &str) -> Result<LatLng, String> {
+    nearest::parse_coordinate(value).ok_or_else(|| "expected `latitude,longitude`".to_owned())
+}
+
+/// Reads `x-heekkr-near` as `latitude,longitude` and `x-heekkr-radius-m`
+/// in metres, defaulting to 0 for no limit.
+#[allow(clippy::result_large_err)]
+fn near(metadata: &MetadataMap) -> Result<Option<(LatLng, f64)>, Status> {
+    let Some(value) = metadata.get("x-heekkr-near") else {
+        return Ok(None);
+    };
+    let origin = value
+        .to_str()
+        .ok()
+        .and_then(nearest::parse_coordinate)
+        .ok_or_else(|| Status::invalid_argument("Invalid x-heekkr-near"))?;
+    let radius_m = match metadata.get("x-heekkr-radius-m") {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|r| *r >= 0.0)
+            .ok_or_else(|| Status::invalid_argument("Invalid x-heekkr-radius-m"))?,
+        None => 0.0,
+    };
+    Ok(Some((origin, radius_m)))
+}
+
+/// Reads the `grpc-timeout` header, ignoring it if malformed.
+fn grpc_timeout(metadata: &MetadataMap) -> Option<Duration> {
+    parse_grpc_timeout(metadata.get("grpc-timeout")?.to_str().ok()?)
+}
+
+/// Parses a timeout such as `500m` or `10S`: at most 8 digits and a unit,
+/// as the gRPC spec allows.
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    let unit = value.chars().last()?;
+    let amount = &value[..value.len() - unit.len_utf8()];
+    if amount.is_empty() || amount.len() > 8 || !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
     }
+    let amount = amount.parse::<u64>().ok()?;
+    Some(match unit {
+        'H' => Duration::from_secs(amount.checked_mul(60 * 60)?),
+        'M' => Duration::from_secs(amount.checked_mul(60)?),
+        'S' => Duration::from_secs(amount),
+        'm' => Duration::from_millis(amount),
+        'u' => Duration::from_micros(amount),
+        'n' => Duration::from_nanos(amount),
+        _ => return None,
+    })
 }
 
-async fn serve(addr: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
-    let resolver = JsonResolver::default();
+async fn serve(
+    addr: SocketAddr,
+    resolvers: Vec<SharedResolver>,
+    geocoder: Arc<Geocoder>,
+    breakers: Breakers,
+    catalogue: Arc<Catalogue>,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let resolvers = Arc::new(resolvers);
+    let breakers = Arc::new(breakers);
+    tokio::spawn(refresh_catalogue(
+        resolvers.clone(),
+        breakers.clone(),
+        catalogue.clone(),
+        config.libraries.clone(),
+    ));
+
+    let resolver = Arc::new(JsonResolver {
+        resolvers: resolvers.clone(),
+        geocoder,
+        breakers: breakers.clone(),
+        catalogue: catalogue.clone(),
+        results: Arc::new(ResultCache::new(&config.search)),
+        search_config: config.search.clone(),
+    });
+    let (reporter, health) = tonic_health::server::health_reporter();
+    health::report(reporter, &breakers, &catalogue).await;
+    let diagnostics = Diagnostics {
+        resolvers,
+        breakers,
+    };
 
     println!("Starting server at {addr}");
     Server::builder()
-        .add_service(resolver_server::ResolverServer::new(resolver))
+        .add_service(resolver_server::ResolverServer::from_arc(resolver.clone()))
+        .add_service(libraries_server::LibrariesServer::from_arc(resolver))
+        .add_service(diagnostics_server::DiagnosticsServer::new(diagnostics))
+        .add_service(health)
         .serve(addr)
         .await?;
 
     Ok(())
 }
 
+/// Loads the catalogue right away, then reloads it every `refresh_secs`
+/// unless zero, so requests rarely wait for upstream.
+async fn refresh_catalogue(
+    resolvers: Arc<Vec<SharedResolver>>,
+    breakers: Arc<Breakers>,
+    catalogue: Arc<Catalogue>,
+    config: CatalogueConfig,
+) {
+    refresh_libraries(&resolvers, &breakers, &catalogue).await;
+    if config.refresh_secs == 0 {
+        return;
+    }
+    let period = Duration::from_secs(config.refresh_secs);
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        refresh_libraries(&resolvers, &breakers, &catalogue).await;
+    }
+}
+
+/// Geocodes every library as if there were no overrides and compares the
+/// result with each override.
+async fn check_overrides(config: &Config, threshold: f64) {
+    let Some(path) = &config.geocode.overrides else {
+        eprintln!("no coordinate overrides configured");
+        process::exit(1);
+    };
+    let overrides = Overrides::load(path).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1);
+    });
+
+    let mut config = config.clone();
+    config.geocode.overrides = None;
+    let resolvers = Geocoder::new(&config.geocode)
+        .and_then(|geocoder| resolver::from_config(&config, &Arc::new(geocoder)))
+        .unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1);
+        });
+    let (libraries, failures) = get_libraries(
+        &resolvers,
+        &Breakers::from_config(&config),
+        &Arc::new(Catalogue::new(&config)),
+        None,
+    )
+    .await;
+    for f in failures {
+        eprintln!("Failed to load {}: {}", f.id, f.kind);
+    }
+
+    for o in overrides.iter() {
+        let address = o.address.as_deref().unwrap_or("");
+        let Some(library) = libraries
+            .iter()
+            .filter_map(|d| d.library.as_ref())
+            .find(|l| l.id == o.id)
+        else {
+            println!("{}\tnot found\t{}", o.id, address);
+            continue;
+        };
+        let Some(c) = &library.coordinate else {
+            println!("{}\tnot geocoded\t{}", o.id, address);
+            continue;
+        };
+        let distance = location::distance_m((o.latitude, o.longitude), (c.latitude, c.longitude));
+        if distance >= threshold {
+            println!(
+                "{}\t{:.0} m\tgeocoded {:.6},{:.6}\toverride {:.6},{:.6}\t{}",
+                o.id, distance, c.latitude, c.longitude, o.latitude, o.longitude, address
+            );
+        }
+    }
+}
+
 fn main() {
     if let Ok(dsn) = env::var("SENTRY_DSN") {
         let _guard = sentry::init((
@@ -90,22 +499,262 @@ fn main() {
         .block_on(async {
             let cli = Cli::parse();
 
+            // Commands talking to a running server need no resolver config.
+            match &cli.command {
+                Commands::LoadTest {
+                    endpoint,
+                    term,
+                    library,
+                    concurrency,
+                    requests,
+                } => {
+                    load_test::run(
+                        endpoint.clone(),
+                        term.clone(),
+                        library.clone(),
+                        *concurrency,
+                        *requests,
+                    )
+                    .await
+                    .unwrap_or_else(|err| exit_with(&*err));
+                    return;
+                }
+                Commands::Status { endpoint } => {
+                    let mut client = DiagnosticsClient::connect(endpoint.clone())
+                        .await
+                        .unwrap_or_else(|err| exit_with(&err));
+                    let response =
+                        client
+                            .get_status(GetStatusRequest {})
+                            .await
+                            .unwrap_or_else(|status| {
+                                eprintln!("{}", status.message());
+                                process::exit(1);
+                            });
+                    for r in response.into_inner().resolvers {
+                        let circuit = match r.circuit() {
+                            CircuitState::Closed => "closed",
+                            CircuitState::Open => "open",
+                            CircuitState::HalfOpen => "half-open",
+                            CircuitState::Unspecified => "unknown",
+                        };
+                        print!("{}\t{}\t{} failures", r.id, circuit, r.consecutive_failures);
+                        if r.retry_after_ms > 0 {
+                            print!("\tretry in {:.1?}", Duration::from_millis(r.retry_after_ms));
+                        }
+                        println!();
+                    }
+                    return;
+                }
+                _ => {}
+            }
+
+            let config = Config::load(&cli.config).unwrap_or_else(|err| {
+                eprintln!("{err}");
+                process::exit(1);
+            });
+            let (geocoder, resolvers) = Geocoder::new(&config.geocode)
+                .map(Arc::new)
+                .and_then(|geocoder| {
+                    let resolvers = resolver::from_config(&config, &geocoder)?;
+                    Ok((geocoder, resolvers))
+                })
+                .unwrap_or_else(|err| {
+                    eprintln!("{err}");
+                    process::exit(1);
+                });
+            let breakers = Breakers::from_config(&config);
+            let catalogue = Arc::new(Catalogue::new(&config));
+
             match &cli.command {
                 Commands::Serve { address } => {
-                    serve(*address).await.unwrap();
+                    serve(*address, resolvers, geocoder, breakers, catalogue, &config)
+                        .await
+                        .unwrap();
                 }
                 Commands::Libraries => {
-                    let libraries = get_libraries().await;
+                    let (libraries, failures) =
+                        get_libraries(&resolvers, &breakers, &catalogue, None).await;
                     println!("{libraries:#?}");
+                    for f in failures {
+                        eprintln!("Failed to load {}: {}", f.id, f.kind);
+                    }
+                }
+                Commands::Nearest {
+                    keyword,
+                    lat,
+                    lng,
+                    radius,
+                    count,
+                    resolver,
+                } => {
+                    let origin = match (lat, lng) {
+                        (Some(latitude), Some(longitude)) => Origin::Coordinate(LatLng {
+                            latitude: *latitude,
+                            longitude: *longitude,
+                        }),
+                        _ => Origin::Keyword(keyword.clone().unwrap_or_default()),
+                    };
+                    let resolvers =
+                        nearest::select(&resolvers, resolver).unwrap_or_else(|status| {
+                            eprintln!("{}", status.message());
+                            process::exit(1);
+                        });
+                    let origin = nearest::locate(&geocoder, Some(origin))
+                        .await
+                        .unwrap_or_else(|status| {
+                            eprintln!("{}", status.message());
+                            process::exit(1);
+                        });
+                    let (libraries, failures) =
+                        get_libraries(&resolvers, &breakers, &catalogue, None).await;
+                    for f in failures {
+                        eprintln!("Failed to load {}: {}", f.id, f.kind);
+                    }
+                    let libraries = nearest::nearest(
+                        libraries,
+                        &origin,
+                        radius.unwrap_or_default(),
+                        count.unwrap_or_default(),
+                    );
+                    for n in libraries {
+                        let Some(detail) = n.detail else { continue };
+                        let Some(library) = detail.library else {
+                            continue;
+                        };
+                        println!(
+                            "{:.0} m\t{}\t{}\t{}",
+                            n.distance_m, library.id, library.name, detail.road_address
+                        );
+                    }
+                }
+                Commands::CheckOverrides { threshold } => {
+                    check_overrides(&config, *threshold).await;
                 }
-                Commands::Search { keyword, library } => {
-                    let mut stream = search(keyword, library).await;
+                Commands::Search {
+                    keyword,
+                    library,
+                    isbn,
+                    title,
+                    author,
+                    publisher,
+                    year_from,
+                    year_to,
+                    merge,
+                    near,
+                    radius,
+                } => {
+                    let parsed = Query::parse(keyword).unwrap_or_else(|err| {
+                        eprintln!("{err}");
+                        process::exit(1);
+                    });
+                    let query = Query {
+                        keyword: parsed.keyword,
+                        isbn: isbn.clone().or(parsed.isbn),
+                        title: title.clone().or(parsed.title),
+                        author: author.clone().or(parsed.author),
+                        publisher: publisher.clone().or(parsed.publisher),
+                        year_from: year_from.or(parsed.year_from),
+                        year_to: year_to.or(parsed.year_to),
+                    };
+                    query.validate().unwrap_or_else(|err| {
+                        eprintln!("{err}");
+                        process::exit(1);
+                    });
+                    let mut options = SearchOptions {
+                        merge: *merge || config.search.merge,
+                        ..Default::default()
+                    };
+                    let mut library = library.clone();
+                    let mut distances = None;
+                    if let Some(origin) = near {
+                        let (ids, d, failures) = nearest::library_ids_near(
+                            &resolvers,
+                            &breakers,
+                            &catalogue,
+                            origin,
+                            radius.unwrap_or_default(),
+                            &library,
+                            None,
+                        )
+                        .await;
+                        library = ids;
+                        distances = Some(d);
+                        options.failures = failures;
+                    }
+                    let results = Arc::new(ResultCache::new(&config.search));
+                    let mut stream =
+                        search(&resolvers, &breakers, &results, query, &library, options).await;
+                    if let Some(distances) = distances {
+                        stream = nearest::order_holdings(stream, distances);
+                    }
                     while let Some(value) = stream.next().await {
-                        if let Ok(response) = value {
-                            println!("{response:#?}");
+                        match value {
+                            Ok(response) => println!("{response:#?}"),
+                            Err(status) => {
+                                for (id, kind) in failure::from_metadata(status.metadata()) {
+                                    eprintln!("Failed to search {id}: {kind}");
+                                }
+                            }
                         }
                     }
                 }
+                // Handled above.
+                Commands::LoadTest { .. } | Commands::Status { .. } => {}
             };
         });
 }
+
+/// Prints an error with its causes, which transport errors leave out of
+/// their own message, and exits.
+fn exit_with(err: &dyn std::error::Error) -> ! {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        // Some errors already repeat their cause in their message.
+        let cause = err.to_string();
+        if !message.ends_with(&cause) {
+            message = format!("{message}: {cause}");
+        }
+        source = err.source();
+    }
+    eprintln!("{message}");
+    process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_grpc_timeouts() {
+        assert_eq!(parse_grpc_timeout("500m"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_grpc_timeout("10S"), Some(Duration::from_secs(10)));
+        assert_eq!(parse_grpc_timeout("2M"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_grpc_timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_grpc_timeout("7u"), Some(Duration::from_micros(7)));
+        assert_eq!(parse_grpc_timeout("7n"), Some(Duration::from_nanos(7)));
+        assert_eq!(
+            parse_grpc_timeout("99999999H"),
+            Some(Duration::from_secs(99_999_999 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_grpc_timeouts() {
+        for value in [
+            "",
+            "S",
+            "10",
+            "10s",
+            "+10S",
+            "-1S",
+            "1.5S",
+            "123456789S",
+            "18446744073709551615S",
+            "10한",
+        ] {
+            assert_eq!(parse_grpc_timeout(value), None, "{value:?}");
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Libraries ordered by distance from a point.
+
+use std::{collections::HashMap, sync::Arc};
+
+use futures::TryStreamExt;
+use heekkr::kr::heek::{LatLng, SearchResponse};
+use tokio::time::Instant;
+use tonic::Status;
+
+use crate::{
+    breaker::Breakers,
+    catalogue::Catalogue,
+    failure::Failure,
+    location::{self, Geocoder},
+    proto::{nearest_libraries_request::Origin, LibraryDetail, NearestLibrary},
+    resolver::SharedResolver,
+    search::get_libraries,
+    SearchResponseStream,
+};
+
+/// Parses `latitude,longitude`, e.g. `37.5665,126.978`.
+pub fn parse_coordinate(value: &str) -> Option<LatLng> {
+    let (latitude, longitude) = value.split_once(',')?;
+    let c = LatLng {
+        latitude: latitude.trim().parse().ok()?,
+        longitude: longitude.trim().parse().ok()?,
+    };
+    ((-90.0..=90.0).contains(&c.latitude) && (-180.0..=180.0).contains(&c.longitude)).then_some(c)
+}
+
+/// The coordinate of `origin`, geocoding keywords like library names.
+pub async fn locate(geocoder: &Geocoder, origin: Option<Origin>) -> Result<LatLng, Status> {
+    match origin {
+        Some(Origin::Coordinate(c)) => {
+            if !(-90.0..=90.0).contains(&c.latitude) || !(-180.0..=180.0).contains(&c.longitude) {
+                return Err(Status::invalid_argument("Coordinate out of range"));
+            }
+            Ok(c)
+        }
+        Some(Origin::Keyword(keyword)) if !keyword.trim().is_empty() => geocoder
+            .search_place(keyword.trim())
+            .await
+            .map(|a| LatLng {
+                latitude: a.y,
+                longitude: a.x,
+            })
+            .ok_or_else(|| Status::not_found(format!("Cannot locate {}", keyword.trim()))),
+        _ => Err(Status::invalid_argument("Origin is empty")),
+    }
+}
+
+/// The resolvers with the given ids, or all of them when none are given.
+#[allow(clippy::result_large_err)]
+pub fn select(resolvers: &[SharedResolver], ids: &[String]) -> Result<Vec<SharedResolver>, Status> {
+    if let Some(id) = ids
+        .iter()
+        .find(|id| !resolvers.iter().any(|r| r.id() == **id))
+    {
+        return Err(Status::invalid_argument(format!("Unknown resolver {id}")));
+    }
+    Ok(resolvers
+        .iter()
+        .filter(|r| ids.is_empty() || ids.contains(&r.id()))
+        .cloned()
+        .collect())
+}
+
+/// Libraries with a coordinate within `radius_m` metres of `origin`, nearest
+/// first, keeping the first `count`. Zero means no limit for either.
+pub fn nearest(
+    libraries: Vec<LibraryDetail>,
+    origin: &LatLng,
+    radius_m: f64,
+    count: usize,
+) -> Vec<NearestLibrary> {
+    let mut nearest = libraries
+        .into_iter()
+        .filter_map(|detail| {
+            let c = detail.library.as_ref()?.coordinate.as_ref()?;
+            let distance_m = location::distance_m(
+                (origin.latitude, origin.longitude),
+                (c.latitude, c.longitude),
+            );
+            (radius_m <= 0.0 || distance_m <= radius_m).then_some(NearestLibrary {
+                detail: Some(detail),
+                distance_m,
+            })
+        })
+        .collect::<Vec<_>>();
+    nearest.sort_by(|a, b| a.distance_m.total_cmp(&b.distance_m));
+    if count > 0 {
+        nearest.truncate(count);
+    }
+    nearest
+}
+
+/// Ids of libraries within `radius_m` metres of `origin`, nearest first,
+/// along with the distance to each. Only `library_ids` are considered unless
+/// empty. Libraries of resolvers whose list fails to load are left out, and
+/// those resolvers returned as failures.
+///
+/// Without a radius, `library_ids` are all kept as given, whether or not
+/// their distance is known; only their holdings are to be ordered by it.
+pub async fn library_ids_near(
+    resolvers: &[SharedResolver],
+    breakers: &Breakers,
+    catalogue: &Arc<Catalogue>,
+    origin: &LatLng,
+    radius_m: f64,
+    library_ids: &[String],
+    deadline: Option<Instant>,
+) -> (Vec<String>, HashMap<String, f64>, Vec<Failure>) {
+    let (libraries, failures) = get_libraries(resolvers, breakers, catalogue, deadline).await;
+    let near = nearest(libraries, origin, radius_m, 0)
+        .into_iter()
+        .filter_map(|n| Some((n.detail?.library?.id, n.distance_m)))
+        .filter(|(id, _)| library_ids.is_empty() || library_ids.contains(id))
+        .collect::<Vec<_>>();
+    let distances = near.iter().cloned().collect();
+    let ids = if radius_m <= 0.0 && !library_ids.is_empty() {
+        library_ids.to_vec()
+    } else {
+        near.into_iter().map(|(id, _)| id).collect()
+    };
+    (ids, distances, failures)
+}
+
+/// Orders the holdings of each entity nearest first, those of libraries at
+/// an unknown distance last.
+pub fn order_holdings(
+    stream: SearchResponseStream,
+    distances: HashMap<String, f64>,
+) -> SearchResponseStream {
+    Box::pin(stream.map_ok(move |mut r: SearchResponse| {
+        for entity in &mut r.entities {
+            entity.holding_summaries.sort_by(|a, b| {
+                let distance = |id: &str| distances.get(id).copied().unwrap_or(f64::INFINITY);
+                distance(&a.library_id).total_cmp(&distance(&b.library_id))
+            });
+        }
+        r
+    }))
+}
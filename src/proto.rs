@@ -0,0 +1,3 @@
+//! Services specific to this resolver, next to the shared `heekkr` spec.
+
+tonic::include_proto!("kr.heek.jsonrs");
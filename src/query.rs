@@ -0,0 +1,242 @@
+use std::fmt;
+
+/// A structured search. Free text goes to `keyword`; resolvers that cannot
+/// search a field natively fold it into the keyword instead.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub keyword: Option<String>,
+    pub isbn: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub publisher: Option<String>,
+    pub year_from: Option<i32>,
+    pub year_to: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Isbn,
+    Title,
+    Author,
+    Publisher,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QueryErrors {
+    InvalidYear { value: String },
+}
+
+impl fmt::Display for QueryErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryErrors::InvalidYear { value } => write!(
+                f,
+                "invalid year {:?}: expected `YYYY` or a range such as `YYYY-YYYY`",
+                value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QueryErrors {}
+
+impl Query {
+    /// Parses a search term such as `isbn:9788936434120` or
+    /// `author:"한강" year:2014-2016 소년`. Unrecognised words become the
+    /// keyword. Either end of a year range may be left open.
+    pub fn parse(term: &str) -> Result<Query, QueryErrors> {
+        let mut query = Query::default();
+        let mut words = vec![];
+        for token in tokenize(term) {
+            let Some((name, value)) = token.split_once(':') else {
+                words.push(token);
+                continue;
+            };
+            let value = value.trim_matches('"').to_owned();
+            match name {
+                "isbn" => query.isbn = Some(value),
+                "title" => query.title = Some(value),
+                "author" => query.author = Some(value),
+                "publisher" => query.publisher = Some(value),
+                "year" => {
+                    let invalid = || QueryErrors::InvalidYear {
+                        value: value.clone(),
+                    };
+                    let year = |y: &str| match y.trim() {
+                        "" => Ok(None),
+                        y => y.parse().map(Some).map_err(|_| invalid()),
+                    };
+                    let (from, to) = value.split_once('-').unwrap_or((&value, &value));
+                    query.year_from = year(from)?;
+                    query.year_to = year(to)?;
+                    if query.year_from.is_none() && query.year_to.is_none() {
+                        return Err(invalid());
+                    }
+                }
+                _ => words.push(token),
+            }
+        }
+        if !words.is_empty() {
+            query.keyword = Some(words.join(" "));
+        }
+        query.validate()?;
+        Ok(query)
+    }
+
+    /// Fails if the year range ends before it starts, e.g. after fields
+    /// were set from elsewhere than a term.
+    pub fn validate(&self) -> Result<(), QueryErrors> {
+        match (self.year_from, self.year_to) {
+            (Some(from), Some(to)) if from > to => Err(QueryErrors::InvalidYear {
+                value: format!("{from}-{to}"),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Free-text keyword for a resolver supporting only the `supported`
+    /// fields; the values of every other field are appended to it. Years
+    /// cannot be expressed as text and are dropped.
+    pub fn fallback_keyword(&self, supported: impl Fn(Field) -> bool) -> String {
+        let mut words = vec![];
+        words.extend(self.keyword.clone());
+        for (field, value) in [
+            (Field::Isbn, &self.isbn),
+            (Field::Title, &self.title),
+            (Field::Author, &self.author),
+            (Field::Publisher, &self.publisher),
+        ] {
+            if !supported(field) {
+                words.extend(value.clone());
+            }
+        }
+        words.join(" ")
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        for (name, value) in [
+            ("isbn", &self.isbn),
+            ("title", &self.title),
+            ("author", &self.author),
+            ("publisher", &self.publisher),
+        ] {
+            if let Some(value) = value {
+                parts.push(format!("{}:\"{}\"", name, value));
+            }
+        }
+        match (self.year_from, self.year_to) {
+            (Some(from), Some(to)) if from == to => parts.push(format!("year:{}", from)),
+            (from, to) if from.is_some() || to.is_some() => parts.push(format!(
+                "year:{}-{}",
+                from.map(|y| y.to_string()).unwrap_or_default(),
+                to.map(|y| y.to_string()).unwrap_or_default()
+            )),
+            _ => {}
+        }
+        parts.extend(self.keyword.clone());
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Splits on whitespace, keeping double-quoted sections together.
+fn tokenize(term: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in term.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_words_become_the_keyword() {
+        assert_eq!(
+            Query::parse("  채식주의자   한강 ").unwrap(),
+            Query {
+                keyword: Some("채식주의자 한강".to_owned()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(Query::parse("").unwrap(), Query::default());
+    }
+
+    #[test]
+    fn parses_fields_and_quoted_values() {
+        assert_eq!(
+            Query::parse(r#"author:"한 강" title:소년 isbn:9788936434120 publisher:창비 이야기"#)
+                .unwrap(),
+            Query {
+                keyword: Some("이야기".to_owned()),
+                isbn: Some("9788936434120".to_owned()),
+                title: Some("소년".to_owned()),
+                author: Some("한 강".to_owned()),
+                publisher: Some("창비".to_owned()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_fields_stay_in_the_keyword() {
+        assert_eq!(
+            Query::parse("note:x y").unwrap().keyword,
+            Some("note:x y".to_owned())
+        );
+    }
+
+    #[test]
+    fn parses_years_and_ranges() {
+        let years = |term| {
+            let q = Query::parse(term).unwrap();
+            (q.year_from, q.year_to)
+        };
+        assert_eq!(years("year:2014"), (Some(2014), Some(2014)));
+        assert_eq!(years("year:2014-2016"), (Some(2014), Some(2016)));
+        assert_eq!(years("year:2014-"), (Some(2014), None));
+        assert_eq!(years("year:-2016"), (None, Some(2016)));
+    }
+
+    #[test]
+    fn rejects_invalid_years() {
+        for term in [
+            "year:soon",
+            "year:2014-later",
+            "year:",
+            "year:-",
+            "year:2016-2014",
+        ] {
+            assert!(
+                matches!(Query::parse(term), Err(QueryErrors::InvalidYear { .. })),
+                "{term}"
+            );
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let query = Query::parse(r#"title:"소년이 온다" year:2014-2016 한강"#).unwrap();
+        assert_eq!(Query::parse(&query.to_string()).unwrap(), query);
+    }
+}
@@ -17,14 +17,27 @@ pub struct LibrariesContents {
 pub struct LibrariesLibrary {
     pub lib_name: String,
     pub manage_code: String,
-    pub group_name: String,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchPayload {
     pub search_keyword: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_isbn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_publisher: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_pub_year_start: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_pub_year_end: Option<i32>,
     pub manage_code: Vec<String>,
+    pub page: u32,
+    pub display: u32,
 }
 
 #[derive(Deserialize)]
@@ -37,6 +50,8 @@ pub struct SearchResponse {
 #[serde(rename_all = "camelCase")]
 pub struct SearchContents {
     pub book_list: Vec<SearchBook>,
+    #[serde(default)]
+    pub total_count: u32,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -59,7 +74,6 @@ pub struct SearchBook {
 
     pub manage_code: String,
     pub reg_code_desc: String,
-    pub reg_no: String,
     pub call_no: String,
     pub loan_status: String,
     pub working_status: String,
@@ -1,51 +1,105 @@
-use std::num::ParseIntError;
+use std::{collections::HashMap, num::ParseIntError, sync::Arc};
 
+use cached::{proc_macro::cached, TimedSizedCache};
+use futures::{stream, StreamExt};
 use heekkr::kr::heek::{
     holding_status::StateOneof, AvailableStatus, Book, Date, DateTime, HoldingStatus,
-    HoldingSummary, OnLoanStatus, SearchEntity, UnavailableStatus,
+    HoldingSummary, OnLoanStatus, PublishDate, SearchEntity, UnavailableStatus,
 };
+use log::warn;
 use reqwest::Client;
+use serde_json::Value;
 use tokio::task::JoinSet;
 use tonic::Status;
 use url::Url;
 
 use super::parse::{LibrariesResponse, SearchBook, SearchPayload, SearchResponse};
 use crate::{
-    location::search_keyword,
-    resolver::{Coordinate, Library},
+    config::{ConfigErrors, EcoPlatform, RequestPolicy},
+    isbn,
+    jsonpath::JsonPath,
+    location::Geocoder,
+    query::Query,
+    resolver::{self, retry, Library, Retries, SearchPages},
 };
 
+/// Detail requests in flight at once for one page of search results.
+const DETAIL_CONCURRENCY: usize = 8;
+
 pub struct Resolver {
     prefix: String,
     search_prefix: String,
-    host: Url,
+    client: Client,
+    geocoder: Arc<Geocoder>,
+    policy: RequestPolicy,
+    retries: Retries,
+    platform: EcoPlatform,
+    libraries_url: Url,
+    search_url: Url,
+    detail_base: Url,
 }
 
 impl Resolver {
-    pub fn new(prefix: &str, search_prefix: &str, host: &str) -> Resolver {
-        return Resolver {
+    /// Fails if `host` cannot be a base for the platform's URLs.
+    pub fn new(
+        prefix: &str,
+        search_prefix: &str,
+        client: Client,
+        geocoder: Arc<Geocoder>,
+        policy: RequestPolicy,
+        platform: EcoPlatform,
+    ) -> Result<Resolver, ConfigErrors> {
+        let join = |path: &str| {
+            platform
+                .host
+                .join(path)
+                .map_err(|err| ConfigErrors::InvalidUrl {
+                    id: prefix.to_owned(),
+                    msg: format!("{:?}: {}", path, err),
+                })
+        };
+        let libraries_url = join("./api/common/libraryInfo")?;
+        let search_url = join("./api/search")?;
+        let detail_base = join("./bookDetail/")?;
+        Ok(Resolver {
             prefix: prefix.to_owned(),
             search_prefix: search_prefix.to_owned(),
-            host: Url::parse(host).unwrap(),
-        };
+            client,
+            geocoder,
+            policy,
+            retries: Retries::default(),
+            platform,
+            libraries_url,
+            search_url,
+            detail_base,
+        })
     }
+}
 
-    pub async fn get_libraries(&self) -> Result<Vec<Library>, Status> {
-        let client = Client::builder()
-            .danger_accept_invalid_certs(true)
-            .build()
-            .unwrap();
+#[tonic::async_trait]
+impl resolver::Resolver for Resolver {
+    fn id(&self) -> String {
+        self.prefix.clone()
+    }
 
-        let response = client
-            .get(self.host.join("./api/common/libraryInfo").unwrap())
-            .send()
+    fn policy(&self) -> &RequestPolicy {
+        &self.policy
+    }
+
+    fn retries(&self) -> u64 {
+        self.retries.get()
+    }
+
+    async fn get_libraries(&self) -> Result<Vec<Library>, Status> {
+        let request = self.client.get(self.libraries_url.clone());
+        let response = retry::send(&self.prefix, &self.policy, &self.retries, request)
             .await
             .map_err(|err| {
                 Status::unavailable(format!("Failed to reach {}. {}", self.prefix, err))
             })?
             .json::<LibrariesResponse>()
             .await
-            .map_err(|_| Status::unavailable("Failed to parse result"))?;
+            .map_err(|_| Status::data_loss("Failed to parse result"))?;
 
         let mut set = JoinSet::new();
         for e in response
@@ -56,15 +110,10 @@ impl Resolver {
         {
             let id = format!("{}:{}", self.prefix, e.manage_code);
             let keyword = format!("{} {}", self.search_prefix, e.lib_name);
+            let geocoder = self.geocoder.clone();
             set.spawn(async move {
-                Library {
-                    id,
-                    name: e.lib_name,
-                    coordinate: search_keyword(&keyword).await.map(|loc| Coordinate {
-                        latitude: loc.y,
-                        longitude: loc.x,
-                    }),
-                }
+                let address = geocoder.locate(&id, &keyword).await;
+                Library::geocoded(id, e.lib_name, address)
             });
         }
 
@@ -76,76 +125,231 @@ impl Resolver {
         Ok(libraries)
     }
 
-    pub async fn search(
+    fn search<'a>(&'a self, query: &'a Query, library_ids: Vec<String>) -> SearchPages<'a> {
+        let manage_codes = library_ids
+            .into_iter()
+            .map(|id| {
+                id.strip_prefix(&format!("{}:", self.prefix))
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect::<Vec<_>>();
+
+        // Pages hold copies rather than titles, so the copies of the last
+        // title on a page may continue on the next one. That title is held
+        // back and grouped with the next page.
+        Box::pin(stream::unfold(Paging::Next(1, vec![]), move |paging| {
+            let manage_codes = manage_codes.clone();
+            async move {
+                let (page, held) = match paging {
+                    Paging::Next(page, held) => (page, held),
+                    Paging::Failed(err) => return Some((Err(err), Paging::Done)),
+                    Paging::Done => return None,
+                };
+                match self.search_page(query, manage_codes, page).await {
+                    Ok((books, total)) => {
+                        let fetched = page * self.platform.page_size;
+                        let more =
+                            !books.is_empty() && fetched < total && page < self.platform.max_pages;
+                        let mut groups = group_copies(held.into_iter().chain(books).collect());
+                        let next = if more {
+                            Paging::Next(page + 1, groups.pop().unwrap_or_default())
+                        } else {
+                            Paging::Done
+                        };
+                        Some((Ok(self.to_entities(groups).await), next))
+                    }
+                    // Copies held back were fetched fine; send them first.
+                    Err(err) if !held.is_empty() => {
+                        Some((Ok(self.to_entities(vec![held]).await), Paging::Failed(err)))
+                    }
+                    Err(err) => Some((Err(err), Paging::Done)),
+                }
+            }
+        }))
+    }
+}
+
+/// Progress of a paged search.
+enum Paging {
+    /// The page to fetch next and copies of a title from the previous page.
+    Next(u32, Vec<SearchBook>),
+    /// Ends the stream with an error after sending what was held back.
+    Failed(Status),
+    Done,
+}
+
+impl Resolver {
+    /// The copies on one page and the total number of copies found.
+    async fn search_page(
         &self,
-        keyword: &str,
-        library_ids: Vec<String>,
-    ) -> Result<Vec<SearchEntity>, Status> {
-        let client = Client::builder()
-            .danger_accept_invalid_certs(true)
-            .build()
-            .unwrap();
-        let response = client
-            .post(self.host.join("./api/search").unwrap())
+        query: &Query,
+        manage_codes: Vec<String>,
+        page: u32,
+    ) -> Result<(Vec<SearchBook>, u32), Status> {
+        let request = self
+            .client
+            .post(self.search_url.clone())
             .json(&SearchPayload {
-                search_keyword: keyword.to_owned(),
-                manage_code: library_ids
-                    .into_iter()
-                    .map(|id| {
-                        id.strip_prefix(&format!("{}:", self.prefix))
-                            .unwrap()
-                            .to_owned()
-                    })
-                    .collect(),
-            })
-            .send()
+                search_keyword: query.keyword.clone().unwrap_or_default(),
+                search_isbn: query.isbn.clone(),
+                search_title: query.title.clone(),
+                search_author: query.author.clone(),
+                search_publisher: query.publisher.clone(),
+                search_pub_year_start: query.year_from,
+                search_pub_year_end: query.year_to,
+                manage_code: manage_codes,
+                page,
+                display: self.platform.page_size,
+            });
+        let response = retry::send(&self.prefix, &self.policy, &self.retries, request)
             .await
             .map_err(|_| Status::unavailable(format!("Failed to reach {}", self.prefix)))?
             .json::<SearchResponse>()
             .await
-            .map_err(|_| Status::unavailable("Failed to parse result"))?;
+            .map_err(|_| Status::data_loss("Failed to parse result"))?;
 
-        let entities = response
-            .contents
-            .book_list
+        Ok((response.contents.book_list, response.contents.total_count))
+    }
+
+    /// One entity per group of copies, with descriptions if configured.
+    async fn to_entities(&self, groups: Vec<Vec<SearchBook>>) -> Vec<SearchEntity> {
+        let urls = groups
+            .iter()
+            .map(|copies| self.detail_url(&copies[0]))
+            .collect::<Vec<_>>();
+        let descriptions = stream::iter(urls)
+            .map(|url| self.fetch_description(url))
+            .buffered(DETAIL_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
+        groups
             .into_iter()
-            .map(|e| {
-                let state = self.parse_state(&e);
-                let url = self
-                    .host
-                    .join(&format!(
-                        "./bookDetail/{}/{}/{}/{}",
-                        e.pub_form_code, e.book_key, e.species_key, e.isbn
-                    ))
-                    .unwrap()
-                    .to_string();
-                SearchEntity {
-                    book: Some(Book {
-                        isbn: e.isbn,
-                        title: e.title,
-                        description: None, // TODO:
-                        author: Some(e.author),
-                        publisher: Some(e.publisher),
-                        publish_date: None, // TODO:
-                    }),
-                    holding_summaries: vec![HoldingSummary {
-                        library_id: format!("{}:{}", self.prefix, e.manage_code),
-                        location: Some(e.reg_code_desc),
-                        call_number: Some(e.call_no),
-                        status: Some(HoldingStatus {
-                            totals: None,
-                            is_requested: Some(e.reservation_count > 0),
-                            requests: Some(e.reservation_count),
-                            requests_available: Some(e.is_active_resv_yn == "Y"),
-                            state_oneof: state,
-                        }),
-                    }],
-                    url,
-                }
-            })
+            .zip(descriptions)
+            .map(|(copies, description)| self.to_entity(copies, description))
+            .collect()
+    }
+
+    fn detail_url(&self, book: &SearchBook) -> Option<Url> {
+        let detail = self.platform.detail.as_ref()?;
+        let path = detail
+            .path
+            .replace("{pub_form_code}", &book.pub_form_code)
+            .replace("{book_key}", &book.book_key)
+            .replace("{species_key}", &book.species_key)
+            .replace("{isbn}", &book.isbn);
+        self.platform.host.join(&path).ok()
+    }
+
+    async fn fetch_description(&self, url: Option<Url>) -> Option<String> {
+        let url = url?;
+        let detail = self.platform.detail.as_ref()?;
+        fetch_detail(
+            &self.client,
+            &self.prefix,
+            &self.policy,
+            &self.retries,
+            url.clone(),
+            &detail.description,
+        )
+        .await
+        .unwrap_or_else(|err| {
+            warn!("Failed to fetch detail {}: {}", url, err);
+            None
+        })
+    }
+
+    fn to_entity(&self, copies: Vec<SearchBook>, description: Option<String>) -> SearchEntity {
+        let mut libraries: Vec<(&str, Vec<&SearchBook>)> = Vec::new();
+        for copy in &copies {
+            match libraries
+                .iter_mut()
+                .find(|(code, _)| *code == copy.manage_code)
+            {
+                Some((_, held)) => held.push(copy),
+                None => libraries.push((&copy.manage_code, vec![copy])),
+            }
+        }
+        let holding_summaries = libraries
+            .into_iter()
+            .map(|(code, held)| self.to_holding(code, &held))
+            .collect();
+
+        let e = &copies[0];
+        let mut url = self.detail_base.clone();
+        // Always a base URL, as `new` joined paths onto it.
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().extend([
+                &e.pub_form_code,
+                &e.book_key,
+                &e.species_key,
+                &e.isbn,
+            ]);
+        }
+        SearchEntity {
+            book: Some(Book {
+                isbn: isbn::normalize(&e.isbn),
+                title: e.title.clone(),
+                description,
+                author: Some(e.author.clone()),
+                publisher: Some(e.publisher.clone()),
+                publish_date: parse_publish_date(&e.pub_year),
+            }),
+            holding_summaries,
+            url: url.to_string(),
+        }
+    }
+
+    fn to_holding(&self, manage_code: &str, copies: &[&SearchBook]) -> HoldingSummary {
+        let first = copies[0];
+        let requests = copies.iter().map(|c| c.reservation_count).sum::<u32>();
+        HoldingSummary {
+            library_id: format!("{}:{}", self.prefix, manage_code),
+            location: Some(first.reg_code_desc.clone()),
+            call_number: Some(first.call_no.clone()),
+            status: Some(HoldingStatus {
+                totals: Some(copies.len() as u32),
+                is_requested: Some(requests > 0),
+                requests: Some(requests),
+                requests_available: Some(copies.iter().any(|c| c.is_active_resv_yn == "Y")),
+                state_oneof: self.merge_states(copies),
+            }),
+        }
+    }
+
+    /// Summarises copies held by one library: available if any copy is,
+    /// otherwise the copy due back first, otherwise the first known state.
+    fn merge_states(&self, copies: &[&SearchBook]) -> Option<StateOneof> {
+        let states = copies
+            .iter()
+            .filter_map(|c| self.parse_state(c))
             .collect::<Vec<_>>();
 
-        Ok(entities)
+        let availables = states
+            .iter()
+            .filter(|s| matches!(s, StateOneof::Available(_)))
+            .count() as u32;
+        if let Some(StateOneof::Available(status)) = states
+            .iter()
+            .find(|s| matches!(s, StateOneof::Available(_)))
+        {
+            return Some(StateOneof::Available(AvailableStatus {
+                detail: status.detail.clone(),
+                availables: Some(availables),
+            }));
+        }
+
+        let on_loan = states
+            .iter()
+            .filter(|s| matches!(s, StateOneof::OnLoan(_)))
+            .min_by_key(|s| match s {
+                StateOneof::OnLoan(OnLoanStatus {
+                    due: Some(DateTime { date: Some(d), .. }),
+                    ..
+                }) => (false, d.year, d.month, d.day),
+                _ => (true, 0, 0, 0),
+            });
+        on_loan.or(states.first()).cloned()
     }
 
     fn parse_state(&self, book: &SearchBook) -> Option<StateOneof> {
@@ -189,3 +393,234 @@ impl Resolver {
         })
     }
 }
+
+/// Groups copies of the same title, keyed by species key and falling back to
+/// a valid ISBN, then the copy's own book key.
+fn group_copies(books: Vec<SearchBook>) -> Vec<Vec<SearchBook>> {
+    let mut groups: Vec<Vec<SearchBook>> = Vec::new();
+    let mut indices: HashMap<String, usize> = HashMap::new();
+    for book in books {
+        let key = [
+            book.species_key.clone(),
+            isbn::parse(&book.isbn).unwrap_or_default(),
+            book.book_key.clone(),
+        ]
+        .into_iter()
+        .find(|k| !k.is_empty())
+        .unwrap_or_default();
+        match indices.get(&key) {
+            Some(&i) => groups[i].push(book),
+            None => {
+                indices.insert(key, groups.len());
+                groups.push(vec![book]);
+            }
+        }
+    }
+    groups
+}
+
+/// Parses publication years such as `2020`, `c2020`, `[2019]`, `2020.3` or
+/// `2020-03-15`, keeping month and day only when they are plausible.
+fn parse_publish_date(value: &str) -> Option<PublishDate> {
+    let mut parts = value
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|p| !p.is_empty());
+    let year = parts.find(|p| p.len() == 4)?.parse().ok()?;
+    let month = parts
+        .next()
+        .and_then(|m| m.parse().ok())
+        .filter(|m| (1..=12).contains(m));
+    let day = month
+        .and(parts.next())
+        .and_then(|d| d.parse().ok())
+        .filter(|d| (1..=31).contains(d));
+    Some(PublishDate { year, month, day })
+}
+
+/// Detail URLs embed the book key, so descriptions are cached per book.
+#[cached(
+    result = true,
+    type = "TimedSizedCache<String, Option<String>>",
+    create = "{ TimedSizedCache::with_size_and_lifespan(10_000, 60 * 60 * 24) }",
+    convert = r#"{ url.to_string() }"#
+)]
+async fn fetch_detail(
+    client: &Client,
+    prefix: &str,
+    policy: &RequestPolicy,
+    retries: &Retries,
+    url: Url,
+    description: &JsonPath,
+) -> Result<Option<String>, reqwest::Error> {
+    let response = retry::send(prefix, policy, retries, client.get(url))
+        .await?
+        .error_for_status()?
+        .json::<Value>()
+        .await?;
+    Ok(description.text(&response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GeocodeConfig;
+
+    fn resolver() -> Resolver {
+        resolver_at("http://127.0.0.1/").unwrap()
+    }
+
+    fn resolver_at(host: &str) -> Result<Resolver, ConfigErrors> {
+        let geocode: GeocodeConfig = toml::from_str("provider = []").unwrap();
+        let platform = EcoPlatform {
+            host: host.parse().unwrap(),
+            page_size: 20,
+            max_pages: 5,
+            detail: None,
+        };
+        Resolver::new(
+            "eco",
+            "eco",
+            Client::new(),
+            Arc::new(Geocoder::new(&geocode).unwrap()),
+            RequestPolicy::default(),
+            platform,
+        )
+    }
+
+    fn book(species_key: &str, manage_code: &str, working_status: &str, due: &str) -> SearchBook {
+        let loan_status = if working_status.is_empty() {
+            "대출가능"
+        } else {
+            "대출불가(대출중)"
+        };
+        SearchBook {
+            title: "Title".to_owned(),
+            author: "Author".to_owned(),
+            publisher: "Publisher".to_owned(),
+            pub_year: "2020".to_owned(),
+            isbn: "9788936434267".to_owned(),
+            species_key: species_key.to_owned(),
+            book_key: format!("{species_key}-{manage_code}-{due}"),
+            pub_form_code: "MO".to_owned(),
+            manage_code: manage_code.to_owned(),
+            reg_code_desc: "General".to_owned(),
+            call_no: "813.6".to_owned(),
+            loan_status: loan_status.to_owned(),
+            working_status: working_status.to_owned(),
+            return_plan_date: due.to_owned(),
+            is_active_resv_yn: "N".to_owned(),
+            reservation_count: 0,
+        }
+    }
+
+    fn due(state: Option<StateOneof>) -> Option<(i32, i32, i32)> {
+        match state {
+            Some(StateOneof::OnLoan(OnLoanStatus {
+                due: Some(DateTime { date: Some(d), .. }),
+                ..
+            })) => Some((d.year, d.month, d.day)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn rejects_hosts_that_cannot_be_a_base() {
+        assert!(matches!(
+            resolver_at("mailto:library@example.com"),
+            Err(ConfigErrors::InvalidUrl { .. })
+        ));
+    }
+
+    #[test]
+    fn groups_copies_of_a_title_across_libraries() {
+        let groups = group_copies(vec![
+            book("1", "MA", "", ""),
+            book("2", "MA", "", ""),
+            book("1", "MB", "대출중", "2024.05.20"),
+            book("1", "MA", "대출중", "2024.05.10"),
+        ]);
+        assert_eq!(groups.len(), 2);
+
+        let entity = resolver().to_entity(groups[0].clone(), None);
+        assert_eq!(
+            entity.url,
+            "http://127.0.0.1/bookDetail/MO/1-MA-/1/9788936434267"
+        );
+        let holdings = entity
+            .holding_summaries
+            .iter()
+            .map(|h| (h.library_id.as_str(), h.status.as_ref().unwrap().totals))
+            .collect::<Vec<_>>();
+        assert_eq!(holdings, [("eco:MA", Some(2)), ("eco:MB", Some(1))]);
+    }
+
+    #[test]
+    fn falls_back_to_valid_isbns_then_book_keys() {
+        let mut same = book("", "MA", "", "");
+        same.book_key = "b1".to_owned();
+        let mut other = book("", "MB", "", "");
+        other.book_key = "b2".to_owned();
+        let mut junk = [book("", "MA", "", ""), book("", "MB", "", "")];
+        for (copy, key) in junk.iter_mut().zip(["b3", "b4"]) {
+            copy.isbn = "없음".to_owned();
+            copy.book_key = key.to_owned();
+        }
+        let [first, second] = junk;
+
+        let groups = group_copies(vec![same, other, first, second]);
+        let sizes = groups.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, [2, 1, 1]);
+    }
+
+    #[test]
+    fn counts_available_copies() {
+        let copies = [
+            book("1", "MA", "", ""),
+            book("1", "MA", "대출중", "2024.05.10"),
+            book("1", "MA", "", ""),
+        ];
+        let copies = copies.iter().collect::<Vec<_>>();
+        match resolver().merge_states(&copies) {
+            Some(StateOneof::Available(status)) => assert_eq!(status.availables, Some(2)),
+            state => panic!("expected available, got {state:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_the_earliest_due_date() {
+        let copies = [
+            book("1", "MA", "대출중", "2024.05.20"),
+            book("1", "MA", "대출중", "unknown"),
+            book("1", "MA", "상호대차중", "2024.05.10"),
+            book("1", "MA", "대출중", "2024.06.01"),
+        ];
+        let copies = copies.iter().collect::<Vec<_>>();
+        assert_eq!(due(resolver().merge_states(&copies)), Some((2024, 5, 10)));
+    }
+
+    fn date(year: i32, month: Option<i32>, day: Option<i32>) -> Option<PublishDate> {
+        Some(PublishDate { year, month, day })
+    }
+
+    #[test]
+    fn parses_publish_dates() {
+        assert_eq!(parse_publish_date("2020"), date(2020, None, None));
+        assert_eq!(parse_publish_date("c2020"), date(2020, None, None));
+        assert_eq!(parse_publish_date("[2019]"), date(2019, None, None));
+        assert_eq!(parse_publish_date("2020.3"), date(2020, Some(3), None));
+        assert_eq!(
+            parse_publish_date("2020-03-15"),
+            date(2020, Some(3), Some(15))
+        );
+        assert_eq!(parse_publish_date(""), None);
+        assert_eq!(parse_publish_date("n.d."), None);
+    }
+
+    #[test]
+    fn drops_implausible_months_and_days() {
+        assert_eq!(parse_publish_date("2020.13"), date(2020, None, None));
+        assert_eq!(parse_publish_date("2020.13.05"), date(2020, None, None));
+        assert_eq!(parse_publish_date("2020-02-40"), date(2020, Some(2), None));
+        assert_eq!(parse_publish_date("2020.0"), date(2020, None, None));
+    }
+}
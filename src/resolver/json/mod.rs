@@ -0,0 +1,3 @@
+pub use resolve::Resolver;
+
+mod resolve;
@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use futures::stream;
+use heekkr::kr::heek::{
+    holding_status::StateOneof, AvailableStatus, Book, Date, DateTime, HoldingStatus,
+    HoldingSummary, OnLoanStatus, SearchEntity, UnavailableStatus,
+};
+use reqwest::Client;
+use serde_json::Value;
+use tokio::task::JoinSet;
+use tonic::Status;
+use url::Url;
+
+use crate::{
+    config::{ConfigErrors, HttpMethod, JsonPlatform, RequestPolicy},
+    isbn,
+    jsonpath::JsonPath,
+    location::Geocoder,
+    query::{Field, Query},
+    resolver::{self, retry, Coordinate, Details, Library, Retries, SearchPages},
+};
+
+pub struct Resolver {
+    prefix: String,
+    search_prefix: String,
+    client: Client,
+    geocoder: Arc<Geocoder>,
+    policy: RequestPolicy,
+    retries: Retries,
+    platform: JsonPlatform,
+    libraries_url: Url,
+    search_url: Url,
+}
+
+impl Resolver {
+    /// Fails if the configured paths do not make URLs against `host`.
+    pub fn new(
+        prefix: &str,
+        search_prefix: &str,
+        client: Client,
+        geocoder: Arc<Geocoder>,
+        policy: RequestPolicy,
+        platform: JsonPlatform,
+    ) -> Result<Resolver, ConfigErrors> {
+        let join = |path: &str| {
+            platform
+                .host
+                .join(path)
+                .map_err(|err| ConfigErrors::InvalidUrl {
+                    id: prefix.to_owned(),
+                    msg: format!("{:?}: {}", path, err),
+                })
+        };
+        let libraries_url = join(&platform.libraries.path)?;
+        let search_url = join(&platform.search.path)?;
+        Ok(Resolver {
+            prefix: prefix.to_owned(),
+            search_prefix: search_prefix.to_owned(),
+            client,
+            geocoder,
+            policy,
+            retries: Retries::default(),
+            platform,
+            libraries_url,
+            search_url,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl resolver::Resolver for Resolver {
+    fn id(&self) -> String {
+        self.prefix.clone()
+    }
+
+    fn policy(&self) -> &RequestPolicy {
+        &self.policy
+    }
+
+    fn retries(&self) -> u64 {
+        self.retries.get()
+    }
+
+    async fn get_libraries(&self) -> Result<Vec<Library>, Status> {
+        let mapping = &self.platform.libraries;
+        let request = self.client.get(self.libraries_url.clone());
+        let response = retry::send(&self.prefix, &self.policy, &self.retries, request)
+            .await
+            .map_err(|err| {
+                Status::unavailable(format!("Failed to reach {}. {}", self.prefix, err))
+            })?
+            .json::<Value>()
+            .await
+            .map_err(|_| Status::data_loss("Failed to parse result"))?;
+
+        let mut set = JoinSet::new();
+        for item in mapping.list.select(&response) {
+            let (Some(code), Some(name)) = (mapping.id.text(item), mapping.name.text(item)) else {
+                continue;
+            };
+            let id = format!("{}:{}", self.prefix, code);
+            let coordinate = match (&mapping.latitude, &mapping.longitude) {
+                (Some(lat), Some(lng)) => lat
+                    .text(item)
+                    .and_then(|v| v.parse().ok())
+                    .zip(lng.text(item).and_then(|v| v.parse().ok()))
+                    .map(|(latitude, longitude)| Coordinate {
+                        latitude,
+                        longitude,
+                    }),
+                _ => None,
+            };
+            let keyword = format!("{} {}", self.search_prefix, name);
+            let geocoder = self.geocoder.clone();
+            set.spawn(async move {
+                match (geocoder.overridden(&id), coordinate) {
+                    (Some(address), _) => Library::geocoded(id, name, Some(address)),
+                    (None, Some(c)) => Library {
+                        id,
+                        name,
+                        coordinate: Some(c),
+                        details: Details::default(),
+                    },
+                    (None, None) => {
+                        let address = geocoder.search_keyword(&keyword).await;
+                        Library::geocoded(id, name, address)
+                    }
+                }
+            });
+        }
+
+        let mut libraries: Vec<Library> = Vec::new();
+        while let Some(Ok(library)) = set.join_next().await {
+            libraries.push(library);
+        }
+
+        Ok(libraries)
+    }
+
+    fn search<'a>(&'a self, query: &'a Query, library_ids: Vec<String>) -> SearchPages<'a> {
+        Box::pin(stream::once(self.search_page(query, library_ids)))
+    }
+}
+
+impl Resolver {
+    async fn search_page(
+        &self,
+        query: &Query,
+        library_ids: Vec<String>,
+    ) -> Result<Vec<SearchEntity>, Status> {
+        let mapping = &self.platform.search;
+        let codes = library_ids
+            .iter()
+            .map(|id| {
+                id.strip_prefix(&format!("{}:", self.prefix))
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect::<Vec<_>>();
+
+        let url = self.search_url.clone();
+        let request = match mapping.method {
+            HttpMethod::Get => self.client.get(url),
+            HttpMethod::Post => self.client.post(url),
+        };
+        let values = self.placeholders(query, &codes);
+        let params = mapping
+            .query
+            .iter()
+            .map(|(k, v)| (k.clone(), fill(v, &values)))
+            .filter(|(_, v)| !v.is_empty())
+            .collect::<Vec<_>>();
+        let request = request.query(&params);
+        let request = match &mapping.body {
+            Some(body) => request.json(&fill_body(body, &values, &codes)),
+            None => request,
+        };
+        let response = retry::send(&self.prefix, &self.policy, &self.retries, request)
+            .await
+            .map_err(|_| Status::unavailable(format!("Failed to reach {}", self.prefix)))?
+            .json::<Value>()
+            .await
+            .map_err(|_| Status::data_loss("Failed to parse result"))?;
+
+        let fields = &mapping.fields;
+        let entities = mapping
+            .list
+            .select(&response)
+            .into_iter()
+            .filter_map(|item| {
+                let title = fields.title.text(item)?;
+                let code = fields.library_code.text(item)?;
+                let text = |path: &Option<JsonPath>| path.as_ref().and_then(|p| p.text(item));
+                let requests = text(&fields.requests).and_then(|r| r.parse::<u32>().ok());
+                let url = text(&fields.url)
+                    .and_then(|u| self.platform.host.join(&u).ok())
+                    .map(|u| u.to_string())
+                    .unwrap_or_default();
+                Some(SearchEntity {
+                    book: Some(Book {
+                        isbn: text(&fields.isbn)
+                            .map(|i| isbn::normalize(&i))
+                            .unwrap_or_default(),
+                        title,
+                        description: text(&fields.description),
+                        author: text(&fields.author),
+                        publisher: text(&fields.publisher),
+                        publish_date: None,
+                    }),
+                    holding_summaries: vec![HoldingSummary {
+                        library_id: format!("{}:{}", self.prefix, code),
+                        location: text(&fields.location),
+                        call_number: text(&fields.call_number),
+                        status: Some(HoldingStatus {
+                            totals: None,
+                            is_requested: requests.map(|r| r > 0),
+                            requests,
+                            requests_available: None,
+                            state_oneof: self
+                                .parse_state(text(&fields.loan_status), text(&fields.due_date)),
+                        }),
+                    }],
+                    url,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(entities)
+    }
+
+    fn placeholders(&self, query: &Query, codes: &[String]) -> Vec<(&'static str, String)> {
+        let year = |y: Option<i32>| y.map(|y| y.to_string()).unwrap_or_default();
+        let supported = |field| self.supports(field);
+        vec![
+            ("{keyword}", query.fallback_keyword(supported)),
+            ("{isbn}", query.isbn.clone().unwrap_or_default()),
+            ("{title}", query.title.clone().unwrap_or_default()),
+            ("{author}", query.author.clone().unwrap_or_default()),
+            ("{publisher}", query.publisher.clone().unwrap_or_default()),
+            ("{year_from}", year(query.year_from)),
+            ("{year_to}", year(query.year_to)),
+            ("{library_codes}", codes.join(",")),
+        ]
+    }
+
+    /// Whether the configured request templates can carry `field`.
+    fn supports(&self, field: Field) -> bool {
+        let name = match field {
+            Field::Isbn => "{isbn}",
+            Field::Title => "{title}",
+            Field::Author => "{author}",
+            Field::Publisher => "{publisher}",
+        };
+        let mapping = &self.platform.search;
+        let body = mapping
+            .body
+            .as_ref()
+            .map(|b| b.to_string())
+            .unwrap_or_default();
+        body.contains(name) || mapping.query.values().any(|v| v.contains(name))
+    }
+
+    fn parse_state(&self, status: Option<String>, due: Option<String>) -> Option<StateOneof> {
+        let status = status?;
+        let mapping = &self.platform.search.status;
+        if mapping.available.contains(&status) {
+            Some(StateOneof::Available(AvailableStatus {
+                detail: Some(status),
+                availables: None,
+            }))
+        } else if mapping.on_loan.contains(&status) {
+            Some(StateOneof::OnLoan(OnLoanStatus {
+                detail: Some(status),
+                due: due.as_deref().and_then(parse_date),
+            }))
+        } else {
+            Some(StateOneof::Unavailable(UnavailableStatus {
+                detail: Some(status),
+            }))
+        }
+    }
+}
+
+fn fill(template: &str, values: &[(&str, String)]) -> String {
+    values.iter().fold(template.to_owned(), |s, (name, value)| {
+        s.replace(name, value)
+    })
+}
+
+fn fill_body(template: &Value, values: &[(&str, String)], codes: &[String]) -> Value {
+    match template {
+        Value::String(s) if s == "{library_codes}" => {
+            Value::Array(codes.iter().cloned().map(Value::String).collect())
+        }
+        Value::String(s) => Value::String(fill(s, values)),
+        Value::Array(items) => {
+            Value::Array(items.iter().map(|v| fill_body(v, values, codes)).collect())
+        }
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), fill_body(v, values, codes)))
+                .collect(),
+        ),
+        v => v.clone(),
+    }
+}
+
+/// Parses dates such as `2023.11.02`, `2023-11-02` or `20231102`.
+fn parse_date(value: &str) -> Option<DateTime> {
+    let digits = value
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>();
+    let (year, month, day) = match digits.as_slice() {
+        [ymd] if ymd.len() == 8 => (&ymd[0..4], &ymd[4..6], &ymd[6..8]),
+        [y, m, d, ..] => (*y, *m, *d),
+        _ => return None,
+    };
+    Some(DateTime {
+        date: Some(Date {
+            year: year.parse().ok()?,
+            month: month.parse().ok()?,
+            day: day.parse().ok()?,
+        }),
+        time: None,
+    })
+}
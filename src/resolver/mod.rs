@@ -1,37 +1,122 @@
+use std::sync::Arc;
+
+use futures::stream::BoxStream;
 use heekkr::kr::heek::SearchEntity;
 use tonic::Status;
 
+use crate::{
+    config::{Config, ConfigErrors, Platform, RequestPolicy},
+    location::{Address, Geocoder},
+    query::Query,
+};
+
 mod eco;
-pub mod seoul_nowon;
-pub mod seoul_seocho;
+mod http;
+mod json;
+mod retry;
+
+pub use http::Fingerprint;
+pub use retry::Retries;
 
 #[derive(Debug)]
 pub struct Library {
     pub id: String,
     pub name: String,
     pub coordinate: Option<Coordinate>,
+    pub details: Details,
+}
+
+/// What geocoding found about a library besides its coordinate.
+#[derive(Debug, Default)]
+pub struct Details {
+    pub place_name: Option<String>,
+    pub road_address: Option<String>,
+    pub lot_address: Option<String>,
+    pub phone: Option<String>,
+}
+
+impl Library {
+    /// A library located at `address`, if it was found.
+    pub fn geocoded(id: String, name: String, address: Option<Address>) -> Library {
+        let Some(address) = address else {
+            return Library {
+                id,
+                name,
+                coordinate: None,
+                details: Details::default(),
+            };
+        };
+        Library {
+            id,
+            name,
+            coordinate: Some(Coordinate {
+                latitude: address.y,
+                longitude: address.x,
+            }),
+            details: Details {
+                place_name: address.place_name,
+                road_address: address.road_address,
+                lot_address: address.lot_address,
+                phone: address.phone,
+            },
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Coordinate {
-    pub latitude: f32,
-    pub longitude: f32,
+    pub latitude: f64,
+    pub longitude: f64,
 }
 
 #[tonic::async_trait]
 pub trait Resolver {
     fn id(&self) -> String;
+    fn policy(&self) -> &RequestPolicy;
+    /// Requests retried since startup.
+    fn retries(&self) -> u64;
     async fn get_libraries(&self) -> Result<Vec<Library>, Status>;
-    async fn search(
-        &self,
-        keyword: &str,
-        library_ids: Vec<String>,
-    ) -> Result<Vec<SearchEntity>, Status>;
+    /// Searches the given libraries, yielding results page by page.
+    fn search<'a>(&'a self, query: &'a Query, library_ids: Vec<String>) -> SearchPages<'a>;
+
+    fn owns(&self, library_id: &str) -> bool {
+        library_id
+            .strip_prefix(&self.id())
+            .is_some_and(|rest| rest.starts_with(':'))
+    }
 }
 
-pub fn all() -> Vec<Box<dyn Resolver + Sync + Send>> {
-    vec![
-        Box::new(seoul_seocho::SeoulSeocho::new()),
-        Box::new(seoul_nowon::SeoulNowon::new()),
-    ]
+pub type SearchPages<'a> = BoxStream<'a, Result<Vec<SearchEntity>, Status>>;
+
+pub type SharedResolver = Arc<dyn Resolver + Sync + Send>;
+
+pub fn from_config(
+    config: &Config,
+    geocoder: &Arc<Geocoder>,
+) -> Result<Vec<SharedResolver>, ConfigErrors> {
+    config
+        .resolvers
+        .iter()
+        .map(|c| -> Result<SharedResolver, ConfigErrors> {
+            let client = http::client(&c.id, &c.http)?;
+            Ok(match &c.platform {
+                Platform::Eco(platform) => Arc::new(eco::Resolver::new(
+                    &c.id,
+                    &c.region,
+                    client,
+                    geocoder.clone(),
+                    c.policy.clone(),
+                    platform.clone(),
+                )?),
+                Platform::Json(platform) => Arc::new(json::Resolver::new(
+                    &c.id,
+                    &c.region,
+                    client,
+                    geocoder.clone(),
+                    c.policy.clone(),
+                    platform.as_ref().clone(),
+                )?),
+            })
+        })
+        .collect()
 }
@@ -0,0 +1,76 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use log::warn;
+use rand::Rng;
+use reqwest::{RequestBuilder, Response};
+use tokio::time::sleep;
+
+use crate::config::RequestPolicy;
+
+/// Retries made by one resolver since startup.
+#[derive(Debug, Default)]
+pub struct Retries(AtomicU64);
+
+impl Retries {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Sends `request`, retrying connection errors, timeouts and 5xx/429
+/// responses as allowed by `policy`, and counting the retries in `retries`.
+/// Only used for idempotent requests.
+pub async fn send(
+    id: &str,
+    policy: &RequestPolicy,
+    retries: &Retries,
+    request: RequestBuilder,
+) -> Result<Response, reqwest::Error> {
+    let request = match policy.request_timeout_ms {
+        Some(ms) => request.timeout(Duration::from_millis(ms)),
+        None => request,
+    };
+
+    let mut attempt = 0;
+    loop {
+        let result = request
+            .try_clone()
+            .expect("request bodies must be cloneable")
+            .send()
+            .await;
+        let reason = match &result {
+            Ok(r) if r.status().is_server_error() || r.status().as_u16() == 429 => {
+                r.status().to_string()
+            }
+            Ok(_) => return result,
+            Err(err) if err.is_connect() || err.is_timeout() || err.is_request() => err.to_string(),
+            Err(_) => return result,
+        };
+        if attempt >= policy.retries {
+            return result;
+        }
+
+        let delay = backoff(policy, attempt);
+        attempt += 1;
+        retries.0.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            "Request to {} failed: {}, retrying in {:?} ({}/{})",
+            id, reason, delay, attempt, policy.retries
+        );
+        sleep(delay).await;
+    }
+}
+
+/// Exponential backoff with equal jitter: half of the delay is fixed, the
+/// other half random.
+fn backoff(policy: &RequestPolicy, attempt: u32) -> Duration {
+    let ms = policy
+        .backoff_ms
+        .saturating_mul(1 << attempt.min(16))
+        .min(policy.max_backoff_ms);
+    let half = ms / 2;
+    Duration::from_millis(half + rand::thread_rng().gen_range(0..=ms - half))
+}
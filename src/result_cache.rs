@@ -0,0 +1,83 @@
+use std::sync::Mutex;
+
+use cached::{Cached, TimedSizedCache};
+use heekkr::kr::heek::SearchEntity;
+
+use crate::{config::SearchConfig, query::Query};
+
+/// Pages of complete, failure-free searches, replayed to identical searches
+/// within a short TTL.
+pub struct ResultCache {
+    pages: Option<Mutex<TimedSizedCache<String, Vec<Vec<SearchEntity>>>>>,
+}
+
+impl ResultCache {
+    /// Disabled when `cache_ttl_secs` or `cache_size` is zero.
+    pub fn new(config: &SearchConfig) -> ResultCache {
+        let enabled = config.cache_ttl_secs > 0 && config.cache_size > 0;
+        ResultCache {
+            pages: enabled.then(|| {
+                Mutex::new(TimedSizedCache::with_size_and_lifespan(
+                    config.cache_size,
+                    config.cache_ttl_secs,
+                ))
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.pages.is_some()
+    }
+
+    /// The query, lowercased with runs of whitespace collapsed, followed by
+    /// the sorted, deduplicated library ids.
+    pub fn key(query: &Query, library_ids: &[String]) -> String {
+        let query = query.to_string().to_lowercase();
+        let mut ids = library_ids.to_vec();
+        ids.sort();
+        ids.dedup();
+        format!(
+            "{}|{}",
+            query.split_whitespace().collect::<Vec<_>>().join(" "),
+            ids.join(",")
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<Vec<SearchEntity>>> {
+        let mut pages = self.pages.as_ref()?.lock().unwrap();
+        pages.cache_get(key).cloned()
+    }
+
+    pub fn store(&self, key: String, pages: Vec<Vec<SearchEntity>>) {
+        if let Some(cache) = &self.pages {
+            cache.lock().unwrap().cache_set(key, pages);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(term: &str, library_ids: &[&str]) -> String {
+        let ids = library_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        ResultCache::key(&Query::parse(term).unwrap(), &ids)
+    }
+
+    #[test]
+    fn key_ignores_case_whitespace_and_library_order() {
+        assert_eq!(
+            key("Rust  Programming", &["a:1", "b:2"]),
+            key(" rust programming ", &["b:2", "a:1", "a:1"])
+        );
+        assert_eq!(
+            key(r#"title:"The  Rust" year:2020"#, &["a:1"]),
+            key(r#"year:2020 title:"the RUST""#, &["a:1"])
+        );
+        assert_ne!(key("rust", &["a:1"]), key("rust", &["a:2"]));
+        assert_ne!(key("rust", &["a:1"]), key("title:rust", &["a:1"]));
+    }
+}
@@ -1,88 +1,778 @@
-use std::{convert::identity, sync::mpsc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use heekkr::kr::heek::{LatLng, Library, SearchResponse};
+use futures::stream;
+use heekkr::kr::heek::{
+    holding_status::StateOneof, HoldingSummary, LatLng, Library, OnLoanStatus, SearchEntity,
+    SearchResponse,
+};
 use log::warn;
-use tokio::{task::JoinSet, time::timeout};
+use tokio::{
+    sync::mpsc,
+    task::JoinSet,
+    time::{timeout_at, Instant},
+};
+use tokio_stream::StreamExt;
 use tonic::Status;
 
 use crate::{
-    resolver::{self, all},
+    breaker::{Breaker, Breakers},
+    catalogue::Catalogue,
+    failure::{self, Failure},
+    isbn,
+    proto::LibraryDetail,
+    query::Query,
+    resolver::SharedResolver,
+    result_cache::ResultCache,
     SearchResponseStream,
 };
 
-pub async fn get_libraries() -> Vec<Library> {
+/// Libraries of every resolver, from the catalogue while they are fresh.
+/// Others are loaded, giving up on each after its `libraries_timeout_ms` or
+/// at `deadline`, whichever comes first, and resolvers whose circuit is open
+/// are not asked at all. Lists that fail to load are served stale if loaded
+/// before, and reported as failures otherwise.
+pub async fn get_libraries(
+    resolvers: &[SharedResolver],
+    breakers: &Breakers,
+    catalogue: &Arc<Catalogue>,
+    deadline: Option<Instant>,
+) -> (Vec<LibraryDetail>, Vec<Failure>) {
+    load_libraries(resolvers, breakers, catalogue, deadline, false).await
+}
+
+/// Reloads every library list into the catalogue, however fresh.
+pub async fn refresh_libraries(
+    resolvers: &[SharedResolver],
+    breakers: &Breakers,
+    catalogue: &Arc<Catalogue>,
+) {
+    load_libraries(resolvers, breakers, catalogue, None, true).await;
+}
+
+async fn load_libraries(
+    resolvers: &[SharedResolver],
+    breakers: &Breakers,
+    catalogue: &Arc<Catalogue>,
+    deadline: Option<Instant>,
+    refresh: bool,
+) -> (Vec<LibraryDetail>, Vec<Failure>) {
     let mut set = JoinSet::new();
-    for r in all() {
-        set.spawn(timeout(Duration::from_secs(5), async move {
-            r.get_libraries().await
-        }));
+    for r in resolvers {
+        let r = r.clone();
+        let breaker = breakers.get(&r.id());
+        let catalogue = catalogue.clone();
+        let timeout_ms = r.policy().libraries_timeout_ms;
+        let started = Instant::now();
+        let deadline = limit(started, timeout_ms, deadline);
+        let own = deadline >= started + Duration::from_millis(timeout_ms);
+        set.spawn(async move {
+            let id = r.id();
+            let res = match catalogue.fresh(&id).filter(|_| !refresh) {
+                Some(libraries) => Ok(libraries),
+                None => load(&r, &breaker, &catalogue, deadline, own, refresh).await,
+            };
+            let res = match (res, catalogue.stale(&id)) {
+                (Err(err), Some(libraries)) => {
+                    warn!(
+                        "Failed to load libraries of {}: {}, serving cached",
+                        id, err
+                    );
+                    Ok(libraries)
+                }
+                (res, _) => res,
+            };
+            (id, res)
+        });
     }
 
-    let mut libraries: Vec<Library> = vec![];
+    let mut libraries: Vec<LibraryDetail> = vec![];
+    let mut failures: Vec<Failure> = vec![];
     while let Some(it) = set.join_next().await {
-        let res = it.unwrap();
+        let (id, res) = it.unwrap();
         match res {
-            Ok(Ok(libs)) => {
-                for l in libs {
-                    let library = Library {
-                        id: l.id,
-                        name: l.name,
-                        resolver_id: "json-rs".to_owned(),
-                        coordinate: l.coordinate.map(|c| LatLng {
-                            latitude: c.latitude as f64,
-                            longitude: c.longitude as f64,
-                        }),
-                    };
-                    libraries.push(library);
-                }
+            Ok(libs) => libraries.extend(libs),
+            Err(e) => {
+                warn!("Failed to load libraries of {}: {}, skipping", id, e);
+                failures.push(Failure::new(&id, &e));
             }
-            Ok(Err(e)) => {
-                warn!("Failed to load libraries: {}, skipping", e);
-            }
-            Err(_) => {}
         }
     }
 
-    libraries
+    (libraries, failures)
 }
 
-pub async fn search(term: &str, library_ids: &Vec<String>) -> SearchResponseStream {
-    let term = term.to_owned();
+/// Loads the libraries of `r` into the catalogue, unless a concurrent call
+/// did so while this one waited for it. Timing out counts against the
+/// breaker only when `deadline` is the resolver's `own` rather than the
+/// client's.
+async fn load(
+    r: &SharedResolver,
+    breaker: &Breaker,
+    catalogue: &Catalogue,
+    deadline: Instant,
+    own: bool,
+    refresh: bool,
+) -> Result<Vec<LibraryDetail>, Status> {
+    let id = r.id();
+    let timed_out = || Status::deadline_exceeded("Timed out");
+    let _loading = timeout_at(deadline, catalogue.lock(&id))
+        .await
+        .map_err(|_| timed_out())?;
+    if let Some(libraries) = catalogue.fresh(&id).filter(|_| !refresh) {
+        return Ok(libraries);
+    }
+
+    if !breaker.allow() {
+        return Err(circuit_open());
+    }
+    let libraries = match timeout_at(deadline, r.get_libraries()).await {
+        Ok(Ok(libraries)) => libraries,
+        Ok(Err(err)) => {
+            breaker.record_failure();
+            return Err(err);
+        }
+        Err(_) => {
+            if own {
+                breaker.record_failure();
+            }
+            return Err(timed_out());
+        }
+    };
+    breaker.record_success();
+
+    let libraries = libraries
+        .into_iter()
+        .map(|l| LibraryDetail {
+            library: Some(Library {
+                id: l.id,
+                name: l.name,
+                resolver_id: "json-rs".to_owned(),
+                coordinate: l.coordinate.map(|c| LatLng {
+                    latitude: c.latitude,
+                    longitude: c.longitude,
+                }),
+            }),
+            place_name: l.details.place_name.unwrap_or_default(),
+            road_address: l.details.road_address.unwrap_or_default(),
+            lot_address: l.details.lot_address.unwrap_or_default(),
+            phone: l.details.phone.unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+    catalogue.store(&id, libraries.clone());
+    Ok(libraries)
+}
+
+fn circuit_open() -> Status {
+    Status::unavailable("Circuit open after repeated failures")
+}
+
+/// Time kept from the client's timeout to report failures before it
+/// expires, at most a tenth of the timeout.
+const DEADLINE_MARGIN: Duration = Duration::from_millis(200);
+
+/// When to stop waiting for resolvers for a client willing to wait
+/// `timeout` from now, e.g. from `grpc-timeout`. `None` if too far off to
+/// represent.
+pub fn deadline(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout - DEADLINE_MARGIN.min(timeout / 10))
+}
+
+/// A resolver's own timeout from `started`, shortened to `deadline` if
+/// sooner.
+fn limit(started: Instant, timeout_ms: u64, deadline: Option<Instant>) -> Instant {
+    let own = started + Duration::from_millis(timeout_ms);
+    deadline.map_or(own, |d| d.min(own))
+}
+
+enum Event {
+    Page(Vec<SearchEntity>),
+    Failed(Vec<Failure>),
+}
+
+/// Pages buffered per search before resolver tasks wait for the client.
+const RESPONSE_BUFFER: usize = 8;
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Combines entities sharing an ISBN into one, re-sending the merged
+    /// entity whenever another resolver or page adds holdings to it.
+    pub merge: bool,
+    /// When the client stops waiting, see [`deadline`]. Resolvers still
+    /// running by then are reported as timed out.
+    pub deadline: Option<Instant>,
+    /// Skips cached results, e.g. for up-to-date loan status. The fresh
+    /// results are still cached.
+    pub no_cache: bool,
+    /// Failures of earlier steps, e.g. loading the library lists a search
+    /// near a location picks libraries from, reported along with those of
+    /// the search.
+    pub failures: Vec<Failure>,
+}
+
+/// A search in progress, collecting its pages for the result cache.
+struct Running {
+    rx: mpsc::Receiver<Event>,
+    /// Dropping these aborts the resolver tasks.
+    _tasks: JoinSet<()>,
+    failures: Vec<Failure>,
+    /// `None` while the cache is disabled.
+    pages: Option<Vec<Vec<SearchEntity>>>,
+    cache: Arc<ResultCache>,
+    key: String,
+}
+
+pub async fn search(
+    resolvers: &[SharedResolver],
+    breakers: &Breakers,
+    cache: &Arc<ResultCache>,
+    mut query: Query,
+    library_ids: &[String],
+    options: SearchOptions,
+) -> SearchResponseStream {
+    if let Some(raw) = query.isbn.take() {
+        query.isbn = Some(isbn::normalize(&raw));
+    }
     let library_ids = library_ids.to_owned();
 
-    let (tx, rx) = mpsc::channel::<Result<SearchResponse, Status>>();
-    for resolver in resolver::all() {
+    let key = ResultCache::key(&query, &library_ids);
+    if let Some(pages) = cache.get(&key).filter(|_| !options.no_cache) {
+        let stream = replay(pages, options.failures);
+        return if options.merge {
+            merge_by_isbn(stream)
+        } else {
+            stream
+        };
+    }
+    let started = Instant::now();
+
+    let (tx, rx) = mpsc::channel::<Event>(RESPONSE_BUFFER);
+    let mut tasks = JoinSet::new();
+    for resolver in resolvers {
+        let resolver = resolver.clone();
         let tx = tx.clone();
-        let term = term.clone();
+        let query = query.clone();
         let library_ids = library_ids
             .iter()
-            .filter(|i| i.starts_with(&resolver.id()))
+            .filter(|i| resolver.owns(i))
             .map(|i| i.to_owned())
             .collect::<Vec<_>>();
         if library_ids.is_empty() {
             continue;
         }
-        tokio::spawn(async move {
-            let result = timeout(
-                Duration::from_secs(15),
-                resolver.search(&term, library_ids.clone()),
-            )
-            .await
-            .map_err(|_| Status::deadline_exceeded(""))
-            .and_then(identity);
-
-            match result {
-                Ok(entities) => {
-                    let _ = tx.send(Ok(SearchResponse { entities }));
-                }
-                Err(err) => {
-                    warn!(
-                        "Failed to search({}, {:?}): {}, skipping",
-                        &term, &library_ids, err
-                    );
+        let timeout_ms = resolver.policy().search_timeout_ms;
+        let deadline = limit(started, timeout_ms, options.deadline);
+        // Running out of the client's time says nothing about the resolver.
+        let own = deadline >= started + Duration::from_millis(timeout_ms);
+        let breaker = breakers.get(&resolver.id());
+        tasks.spawn(async move {
+            let err = if breaker.allow() {
+                let mut pages = resolver.search(&query, library_ids.clone());
+                loop {
+                    match timeout_at(deadline, pages.next()).await {
+                        Ok(Some(Ok(entities))) => {
+                            if tx.send(Event::Page(entities)).await.is_err() {
+                                return;
+                            }
+                        }
+                        Ok(Some(Err(err))) => {
+                            breaker.record_failure();
+                            break err;
+                        }
+                        Ok(None) => {
+                            breaker.record_success();
+                            return;
+                        }
+                        Err(_) => {
+                            if own {
+                                breaker.record_failure();
+                            }
+                            break Status::deadline_exceeded("Timed out");
+                        }
+                    }
                 }
+            } else {
+                circuit_open()
             };
+            warn!(
+                "Failed to search({}, {:?}): {}, skipping",
+                &query, &library_ids, err
+            );
+            let failures = library_ids
+                .iter()
+                .map(|id| Failure::new(id, &err))
+                .collect();
+            let _ = tx.send(Event::Failed(failures)).await;
+        });
+    }
+    drop(tx);
+
+    // The stream owns the tasks, so dropping it, e.g. when the client goes
+    // away, aborts any still running along with their HTTP requests.
+    // Failures are reported once every resolver has finished; only searches
+    // without any are cached.
+    let running = Running {
+        rx,
+        _tasks: tasks,
+        failures: options.failures,
+        pages: cache.is_enabled().then(Vec::new),
+        cache: cache.clone(),
+        key,
+    };
+    let stream: SearchResponseStream =
+        Box::pin(stream::unfold(Some(running), |state| async move {
+            let mut running = state?;
+            loop {
+                match running.rx.recv().await {
+                    Some(Event::Page(entities)) => {
+                        if let Some(pages) = &mut running.pages {
+                            pages.push(entities.clone());
+                        }
+                        return Some((Ok(SearchResponse { entities }), Some(running)));
+                    }
+                    Some(Event::Failed(f)) => running.failures.extend(f),
+                    None if running.failures.is_empty() => {
+                        if let Some(pages) = running.pages {
+                            running.cache.store(running.key, pages);
+                        }
+                        return None;
+                    }
+                    None => {
+                        return Some((Err(failure::trailer(&running.failures).await), None));
+                    }
+                }
+            }
+        }));
+    if options.merge {
+        merge_by_isbn(stream)
+    } else {
+        stream
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn replay(pages: Vec<Vec<SearchEntity>>, failures: Vec<Failure>) -> SearchResponseStream {
+    let pages = stream::iter(
+        pages
+            .into_iter()
+            .map(|entities| Ok(SearchResponse { entities })),
+    );
+    if failures.is_empty() {
+        return Box::pin(pages);
+    }
+    Box::pin(pages.chain(stream::once(async move {
+        Err(failure::trailer(&failures).await)
+    })))
+}
+
+/// Each response carries only the entities it changed; clients replace
+/// entities they already hold by ISBN.
+#[allow(clippy::result_large_err)]
+fn merge_by_isbn(stream: SearchResponseStream) -> SearchResponseStream {
+    let mut merged: HashMap<String, SearchEntity> = HashMap::new();
+    Box::pin(stream.map(move |response| {
+        response.map(|r| SearchResponse {
+            entities: merge_entities(&mut merged, r.entities),
+        })
+    }))
+}
+
+fn merge_entities(
+    merged: &mut HashMap<String, SearchEntity>,
+    entities: Vec<SearchEntity>,
+) -> Vec<SearchEntity> {
+    let mut changed: Vec<String> = vec![];
+    let mut unkeyed: Vec<SearchEntity> = vec![];
+    for entity in entities {
+        // Junk such as `없음` or a bad check digit is not a key; books
+        // sharing it are unrelated.
+        let Some(key) = entity.book.as_ref().and_then(|b| isbn::parse(&b.isbn)) else {
+            unkeyed.push(entity);
+            continue;
+        };
+        let entity = match merged.remove(&key) {
+            Some(existing) => merge_entity(existing, entity),
+            None => entity,
+        };
+        merged.insert(key.clone(), entity);
+        if !changed.contains(&key) {
+            changed.push(key);
+        }
+    }
+    changed
+        .iter()
+        .map(|key| merged[key].clone())
+        .chain(unkeyed)
+        .collect()
+}
+
+fn merge_entity(mut into: SearchEntity, from: SearchEntity) -> SearchEntity {
+    if let (Some(book), Some(other)) = (into.book.as_mut(), from.book) {
+        book.description = book.description.take().or(other.description);
+        book.author = book.author.take().or(other.author);
+        book.publisher = book.publisher.take().or(other.publisher);
+        book.publish_date = book.publish_date.take().or(other.publish_date);
+    }
+    for holding in from.holding_summaries {
+        match into
+            .holding_summaries
+            .iter_mut()
+            .find(|h| h.library_id == holding.library_id)
+        {
+            Some(h) => merge_holding(h, holding),
+            None => into.holding_summaries.push(holding),
+        }
+    }
+    if into.url.is_empty() {
+        into.url = from.url;
+    }
+    into
+}
+
+/// Combines two holdings of one library, e.g. copies of a title split
+/// across pages or records: counts add up and the better state wins.
+fn merge_holding(into: &mut HoldingSummary, from: HoldingSummary) {
+    into.location = into.location.take().or(from.location);
+    into.call_number = into.call_number.take().or(from.call_number);
+    let Some(other) = from.status else {
+        return;
+    };
+    let Some(status) = into.status.as_mut() else {
+        into.status = Some(other);
+        return;
+    };
+    status.totals = add(status.totals, other.totals);
+    status.requests = add(status.requests, other.requests);
+    status.is_requested = any(status.is_requested, other.is_requested);
+    status.requests_available = any(status.requests_available, other.requests_available);
+    status.state_oneof = merge_state(status.state_oneof.take(), other.state_oneof);
+}
+
+/// Available beats on loan, which beats anything else; loans due back
+/// sooner beat later ones.
+fn merge_state(a: Option<StateOneof>, b: Option<StateOneof>) -> Option<StateOneof> {
+    use StateOneof::{Available, OnLoan};
+    match (a, b) {
+        (Some(Available(mut a)), Some(Available(b))) => {
+            a.availables = add(a.availables, b.availables);
+            Some(Available(a))
+        }
+        (Some(s @ Available(_)), _) | (_, Some(s @ Available(_))) => Some(s),
+        (Some(OnLoan(a)), Some(OnLoan(b))) => Some(OnLoan(if due(&b) < due(&a) { b } else { a })),
+        (Some(s @ OnLoan(_)), _) | (_, Some(s @ OnLoan(_))) => Some(s),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Orders loans by due date, those without one last.
+fn due(status: &OnLoanStatus) -> (bool, i32, i32, i32) {
+    match status.due.as_ref().and_then(|d| d.date.as_ref()) {
+        Some(d) => (false, d.year, d.month, d.day),
+        None => (true, 0, 0, 0),
+    }
+}
+
+fn add(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    a.zip(b).map(|(a, b)| a + b).or(a).or(b)
+}
+
+fn any(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    a.zip(b).map(|(a, b)| a || b).or(a).or(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use heekkr::kr::heek::{AvailableStatus, Book, Date, DateTime, HoldingStatus};
+    use tokio::time::{sleep, Instant};
+
+    use super::*;
+    use crate::{
+        config::{Config, RequestPolicy, SearchConfig},
+        resolver::{Library, Resolver, SearchPages},
+    };
+
+    const PAGES: usize = 3;
+    const PAGE_DELAY: Duration = Duration::from_millis(100);
+
+    /// Answers every search with a few pages, each after a delay, like a
+    /// slow upstream.
+    struct SlowResolver {
+        policy: RequestPolicy,
+    }
+
+    #[tonic::async_trait]
+    impl Resolver for SlowResolver {
+        fn id(&self) -> String {
+            "slow".to_owned()
+        }
+
+        fn policy(&self) -> &RequestPolicy {
+            &self.policy
+        }
+
+        fn retries(&self) -> u64 {
+            0
+        }
+
+        async fn get_libraries(&self) -> Result<Vec<Library>, Status> {
+            Ok(vec![])
+        }
+
+        fn search<'a>(&'a self, _query: &'a Query, _library_ids: Vec<String>) -> SearchPages<'a> {
+            Box::pin(stream::iter(0..PAGES).then(|_| async {
+                sleep(PAGE_DELAY).await;
+                Ok(vec![SearchEntity::default()])
+            }))
+        }
+    }
+
+    fn breakers(failure_threshold: u32) -> Breakers {
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [[resolver]]
+            id = "slow"
+            kind = "eco"
+            region = "x"
+            host = "http://127.0.0.1/"
+            breaker.failure_threshold = {failure_threshold}
+            "#
+        ))
+        .unwrap();
+        Breakers::from_config(&config)
+    }
+
+    /// Many concurrent searches against slow resolvers finish in about the
+    /// time of one, while a timer on the same runtime keeps firing on time.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_searches_do_not_starve_the_runtime() {
+        const SEARCHES: usize = 500;
+        const TICK: Duration = Duration::from_millis(10);
+
+        let resolvers: Vec<SharedResolver> = vec![Arc::new(SlowResolver {
+            policy: RequestPolicy::default(),
+        })];
+        let breakers = breakers(5);
+        let cache = Arc::new(ResultCache::new(&SearchConfig::default()));
+
+        let (stop, mut stopped) = tokio::sync::oneshot::channel::<()>();
+        let heartbeat = tokio::spawn(async move {
+            let mut max_lag = Duration::ZERO;
+            while stopped.try_recv().is_err() {
+                let started = Instant::now();
+                sleep(TICK).await;
+                max_lag = max_lag.max(started.elapsed() - TICK);
+            }
+            max_lag
         });
+
+        let started = Instant::now();
+        let pages = futures::future::join_all((0..SEARCHES).map(|i| {
+            let (resolvers, breakers, cache) = (&resolvers, &breakers, &cache);
+            async move {
+                let query = Query {
+                    keyword: Some(format!("term {i}")),
+                    ..Default::default()
+                };
+                let ids = vec!["slow:1".to_owned()];
+                search(resolvers, breakers, cache, query, &ids, Default::default())
+                    .await
+                    .count()
+                    .await
+            }
+        }))
+        .await;
+        let elapsed = started.elapsed();
+
+        assert!(pages.iter().all(|p| *p == PAGES));
+        // Serially, or with a worker blocked per search, this would take
+        // minutes.
+        assert!(
+            elapsed < PAGE_DELAY * PAGES as u32 * 5,
+            "searches took {elapsed:?}"
+        );
+        let _ = stop.send(());
+        let max_lag = heartbeat.await.unwrap();
+        assert!(
+            max_lag < Duration::from_millis(200),
+            "timer fired {max_lag:?} late"
+        );
+    }
+
+    #[test]
+    fn deadline_keeps_a_margin_before_the_client_timeout() {
+        let keeps = |timeout: Duration, margin: Duration| {
+            let before = Instant::now();
+            let d = deadline(timeout).unwrap();
+            before + timeout - margin <= d && d <= Instant::now() + timeout - margin
+        };
+        assert!(keeps(Duration::from_secs(10), DEADLINE_MARGIN));
+        assert!(keeps(Duration::from_secs(1), Duration::from_millis(100)));
+        assert!(keeps(Duration::ZERO, Duration::ZERO));
+        assert_eq!(deadline(Duration::MAX), None);
+    }
+
+    #[test]
+    fn limit_is_the_sooner_of_the_own_timeout_and_the_deadline() {
+        let started = Instant::now();
+        let s = Duration::from_secs;
+        assert_eq!(limit(started, 3000, None), started + s(3));
+        assert_eq!(limit(started, 3000, Some(started + s(10))), started + s(3));
+        assert_eq!(
+            limit(started, 30_000, Some(started + s(10))),
+            started + s(10)
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_resolver_timing_out_opens_the_circuit() {
+        let ids = &["slow:1".to_owned()];
+        let search_with = |search_timeout_ms: u64, deadline: Option<Instant>| async move {
+            let resolvers: Vec<SharedResolver> = vec![Arc::new(SlowResolver {
+                policy: RequestPolicy {
+                    search_timeout_ms,
+                    ..Default::default()
+                },
+            })];
+            let breakers = breakers(1);
+            let options = SearchOptions {
+                deadline,
+                no_cache: true,
+                ..Default::default()
+            };
+            let cache = Arc::new(ResultCache::new(&SearchConfig::default()));
+            let failed = search(
+                &resolvers,
+                &breakers,
+                &cache,
+                Query::default(),
+                ids,
+                options,
+            )
+            .await
+            .any(|r| async move { r.is_err() })
+            .await;
+            (failed, breakers.get("slow").allow())
+        };
+
+        // The client gives up first.
+        assert_eq!(
+            search_with(10_000, deadline(Duration::from_millis(50))).await,
+            (true, true)
+        );
+        // The resolver's own timeout expires.
+        assert_eq!(
+            search_with(50, deadline(Duration::from_secs(10))).await,
+            (true, false)
+        );
+    }
+
+    fn entity(isbn: &str, library_id: &str) -> SearchEntity {
+        SearchEntity {
+            book: Some(Book {
+                isbn: isbn.to_owned(),
+                title: format!("{isbn} at {library_id}"),
+                ..Default::default()
+            }),
+            holding_summaries: vec![HoldingSummary {
+                library_id: library_id.to_owned(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merge_keeps_books_with_invalid_isbns_apart() {
+        let mut merged = HashMap::new();
+        let entities = merge_entities(
+            &mut merged,
+            vec![
+                entity("없음", "a:1"),
+                entity("없음", "a:2"),
+                entity("-", "a:3"),
+                entity("-", "a:4"),
+                entity("9788936434121", "a:5"),
+                entity("9788936434121", "a:6"),
+                entity("9788936434120", "a:7"),
+                entity("89-364-3412-8", "a:8"),
+            ],
+        );
+
+        assert_eq!(entities.len(), 7);
+        assert_eq!(entities[0].holding_summaries.len(), 2);
+        assert!(entities[1..].iter().all(|e| e.holding_summaries.len() == 1));
+    }
+
+    fn holding(totals: u32, requests: u32, state: StateOneof) -> HoldingSummary {
+        HoldingSummary {
+            library_id: "a:1".to_owned(),
+            status: Some(HoldingStatus {
+                totals: Some(totals),
+                is_requested: Some(requests > 0),
+                requests: Some(requests),
+                requests_available: Some(false),
+                state_oneof: Some(state),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn on_loan(day: i32) -> StateOneof {
+        StateOneof::OnLoan(OnLoanStatus {
+            due: Some(DateTime {
+                date: Some(Date {
+                    year: 2024,
+                    month: 5,
+                    day,
+                }),
+                time: None,
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn available(availables: u32) -> StateOneof {
+        StateOneof::Available(AvailableStatus {
+            detail: None,
+            availables: Some(availables),
+        })
+    }
+
+    fn merged_status(holdings: Vec<HoldingSummary>) -> HoldingStatus {
+        let entities = holdings
+            .into_iter()
+            .map(|h| SearchEntity {
+                holding_summaries: vec![h],
+                ..entity("9788936434120", "a:1")
+            })
+            .collect();
+        let entities = merge_entities(&mut HashMap::new(), entities);
+        assert_eq!(entities.len(), 1);
+        assert_eq!(entities[0].holding_summaries.len(), 1);
+        entities[0].holding_summaries[0].status.clone().unwrap()
+    }
+
+    #[test]
+    fn merge_adds_up_holdings_of_one_library() {
+        let status = merged_status(vec![
+            holding(3, 1, on_loan(20)),
+            holding(2, 0, available(1)),
+            holding(1, 2, available(1)),
+        ]);
+        assert_eq!(status.totals, Some(6));
+        assert_eq!(status.requests, Some(3));
+        assert_eq!(status.is_requested, Some(true));
+        assert_eq!(status.state_oneof, Some(available(2)));
+
+        let status = merged_status(vec![
+            holding(1, 0, on_loan(20)),
+            holding(1, 0, on_loan(10)),
+            holding(1, 0, on_loan(15)),
+        ]);
+        assert_eq!(status.totals, Some(3));
+        assert_eq!(status.is_requested, Some(false));
+        assert_eq!(status.state_oneof, Some(on_loan(10)));
     }
-    Box::pin(tokio_stream::iter(rx))
 }